@@ -0,0 +1,24 @@
+// Helpers for the days that take command line options. Bad arguments end the
+// program with a message, like everywhere else in `main`.
+
+use std::process;
+
+pub fn next_number<'a, T: std::str::FromStr>(iter: &mut impl Iterator<Item = &'a String>, option: &str) -> T {
+    match iter.next().map(|n| n.parse::<T>()) {
+        Some(Ok(n)) => n,
+        _ => {
+            println!("{} needs a number", option);
+            process::exit(1);
+        }
+    }
+}
+
+pub fn next_path<'a>(iter: &mut impl Iterator<Item = &'a String>, option: &str) -> &'a str {
+    match iter.next() {
+        Some(path) => path,
+        None => {
+            println!("{} needs a path", option);
+            process::exit(1);
+        }
+    }
+}
@@ -1,163 +1,376 @@
-use std::fs;
-
-enum Direction {
-    Top,
-    Bottom,
-    Left,
-    Right,
-}
-
-fn parse_input(input_file: &str) -> Vec<Vec<u32>> {
-    let mut output: Vec<Vec<u32>> = Vec::<Vec<u32>>::new();
-    let content = fs::read_to_string(input_file).unwrap();
-    for line in content.lines() {
-        let row: Vec<u32> = line.chars().map(|x| x.to_digit(10).unwrap()).collect::<Vec<u32>>();
-        output.push(row);
-    }
-    output
-}
-
-fn check_visible_in_direction(map: &Vec<Vec<u32>>, pos_x: usize, pos_y: usize, direction: Direction) -> bool {
-    let len_x = map.len()-1;
-    let len_y = map[0].len()-1;
-    let elem = map[pos_x][pos_y];
-    match direction {
-        Direction::Top => {
-            for x in (0..pos_x).rev() {
-                if map[x][pos_y] >= elem{
-                    return false;
-                }
-            }
-            return true;
-        },
-        Direction::Bottom => {
-            for x in pos_x+1..=len_x {
-                if map[x][pos_y] >= elem {
-                    return false;
-                }
-            }
-            return true
-        },
-        Direction::Left => {
-            for y in (0..pos_y).rev() {
-                if map[pos_x][y] >= elem {
-                    return false;
-                }
-            }
-            return true;
-        },
-        Direction::Right => {
-            for y in pos_y+1..=len_y {
-                if map[pos_x][y] >= elem {
-                    return false;
-                }
-            }
-            return true;
-        },
+use std::fmt;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::process;
+
+use crate::cli::{next_number, next_path};
+use crate::grid::{Direction, Grid, GridError, ALL_DIRECTIONS, DIAGONALS, DIRECTIONS};
+use crate::image::{self, upscale};
+
+mod render;
+
+use render::{colors, render_ansi, Layer};
+
+// Which lines of sight count, both for looking at a tree from outside the
+// forest and for looking out from a tree house.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DirectionSet {
+    Orthogonal,
+    Diagonal,
+    All,
+}
+
+impl DirectionSet {
+    fn directions(&self) -> &'static [Direction] {
+        match self {
+            DirectionSet::Orthogonal => &DIRECTIONS,
+            DirectionSet::Diagonal => &DIAGONALS,
+            DirectionSet::All => &ALL_DIRECTIONS,
+        }
     }
 }
 
-fn count_visible_in_direction(map: &Vec<Vec<u32>>, pos_x: usize, pos_y: usize, direction: Direction) -> u32 {
-    let len_x = map.len()-1;
-    let len_y = map[0].len()-1;
-    let elem = map[pos_x][pos_y];
-    let mut count = 0;
-    match direction {
-        Direction::Top => {
-            for x in (0..pos_x).rev() {
-                count += 1;
-                if map[x][pos_y] >= elem{
-                    return count;
-                }
-            }
-            return count;
-        },
-        Direction::Bottom => {
-            for x in pos_x+1..=len_x {
-                count += 1;
-                if map[x][pos_y] >= elem {
-                    return count;
-                }
-            }
-            return count;
-        },
-        Direction::Left => {
-            for y in (0..pos_y).rev() {
-                count += 1;
-                if map[pos_x][y] >= elem {
-                    return count;
-                }
-            }
-            return count
-        },
-        Direction::Right => {
-            for y in pos_y+1..=len_y {
-                count += 1;
-                if map[pos_x][y] >= elem {
-                    return count;
-                }
-            }
-            return count;
-        },
+// The rules of a puzzle variant. With a `limit`, nobody sees further than
+// that many trees along a line of sight: from outside only the first `limit`
+// trees of a line can be visible, and a tree house counts at most `limit`
+// trees per direction. The original puzzle looks in the four orthogonal
+// directions without a limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rules {
+    directions: DirectionSet,
+    limit: Option<usize>,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules { directions: DirectionSet::Orthogonal, limit: None }
+    }
+}
+
+impl Rules {
+    fn within_limit(&self, distance: isize) -> bool {
+        self.limit.is_none_or(|limit| distance < limit as isize)
+    }
+
+    fn limited(&self, distance: isize) -> isize {
+        self.limit.map_or(distance, |limit| distance.min(limit as isize))
     }
 }
 
-fn count_trees_in_view(map: &Vec<Vec<u32>>, pos_x: usize, pos_y: usize) -> u32 {
-    count_visible_in_direction(&map, pos_x, pos_y, Direction::Top)  *
-        count_visible_in_direction(&map, pos_x, pos_y, Direction::Bottom) *
-        count_visible_in_direction(&map, pos_x, pos_y, Direction::Left) *
-        count_visible_in_direction(&map, pos_x, pos_y, Direction::Right)
+// Maps come either as rows of digits like the puzzle input, or as rows of
+// whitespace separated numbers, which allows heights above 9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Digits,
+    Numbers,
 }
 
-fn is_visible(map: &Vec<Vec<u32>>, pos_x: usize, pos_y: usize) -> bool {
-    if pos_x == 0 || pos_y == 0 {
-        return true;
+impl Format {
+    // Numbers as soon as any row has whitespace between its trees. A map
+    // that is a single column of numbers needs to be asked for explicitly.
+    fn detect(content: &str) -> Format {
+        if content.lines().any(|line| line.trim().contains(char::is_whitespace)) {
+            Format::Numbers
+        } else {
+            Format::Digits
+        }
     }
-    let len_x = map.len()-1;
-    let len_y = map[0].len()-1;
-    if pos_x == len_x || pos_y == len_y {
-        return true;
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MapError {
+    Ragged { line: usize, expected: usize, found: usize },
+    InvalidHeight { line: usize, column: usize, token: String },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Ragged { line, expected, found } => {
+                write!(f, "line {}: expected {} trees like the first row, found {}", line, expected, found)
+            },
+            MapError::InvalidHeight { line, column, token } => {
+                write!(f, "line {}, tree {}: invalid height \"{}\"", line, column, token)
+            },
+        }
     }
-    check_visible_in_direction(&map, pos_x, pos_y, Direction::Top)  ||
-        check_visible_in_direction(&map, pos_x, pos_y, Direction::Bottom) ||
-        check_visible_in_direction(&map, pos_x, pos_y, Direction::Left) ||
-        check_visible_in_direction(&map, pos_x, pos_y, Direction::Right)
 }
 
-fn solve_part1(input: &str) -> u32 {
-    let map = parse_input(input);
-    let len_x = map.len();
-    let len_y = map[0].len();
-    let mut sum = 0;
-    for x in 0..len_x {
-        for y in 0..len_y {
-            if is_visible(&map, x, y) {
-                sum += 1
+// Blank lines are skipped, but errors refer to the line numbers of the input.
+fn parse_map(content: &str, format: Format) -> Result<Grid<u32>, MapError> {
+    let mut line_numbers: Vec<usize> = Vec::new();
+    let mut rows: Vec<Vec<u32>> = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let tokens: Vec<String> = match format {
+            Format::Digits => line.chars().map(|c| c.to_string()).collect(),
+            Format::Numbers => line.split_whitespace().map(|t| t.to_string()).collect(),
+        };
+        let mut row = Vec::with_capacity(tokens.len());
+        for (j, token) in tokens.into_iter().enumerate() {
+            match token.parse::<u32>() {
+                Ok(height) => row.push(height),
+                Err(_) => return Err(MapError::InvalidHeight { line: i + 1, column: j + 1, token }),
             }
         }
+        line_numbers.push(i + 1);
+        rows.push(row);
     }
-    sum
+    Grid::from_rows(rows).map_err(|error| match error {
+        GridError::Ragged { row, expected, found } => MapError::Ragged { line: line_numbers[row], expected, found },
+    })
 }
 
-fn solve_part2(input: &str) -> u32 {
-    let map = parse_input(input);
-    let len_x = map.len();
-    let len_y = map[0].len();
-    let mut max = 0;
-    for x in 0..len_x {
-        for y in 0..len_y {
-            let count = count_trees_in_view(&map, x, y);
-            if count >= max {
-                max = count;
-            }
+fn read_or_exit(input_file: &str) -> String {
+    match fs::read_to_string(input_file) {
+        Ok(content) => content,
+        Err(e) => {
+            println!("Error reading {}: {}", input_file, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_input(input_file: &str) -> Result<Grid<u32>, MapError> {
+    let content = read_or_exit(input_file);
+    parse_map(&content, Format::detect(&content))
+}
+
+// Visits every tree such that, looking in `direction`, all trees between a
+// tree and the edge have been visited before it: the map is swept from the
+// opposite edge. `visit` gets the line of sight number (below `line_count`),
+// the position along that line (increasing by one per step, but not
+// starting at zero) and the tree's row and column. The map is read row by
+// row in either order, which keeps the sweeps cache friendly. Diagonal lines
+// have one tree per row, so the order within a row does not matter for them.
+fn sweep<F: FnMut(usize, isize, usize, usize)>(map: &Grid<u32>, direction: &Direction, mut visit: F) {
+    let (look_row, look_column) = direction.step();
+    let (dr, dc) = (-look_row, -look_column);
+    let offset = (map.height() + map.width()) as isize;
+    let rows: Vec<usize> = if dr < 0 { (0..map.height()).rev().collect() } else { (0..map.height()).collect() };
+    let columns: Vec<usize> = if dr == 0 && dc < 0 { (0..map.width()).rev().collect() } else { (0..map.width()).collect() };
+    for &row in &rows {
+        for &column in &columns {
+            let (r, c) = (row as isize, column as isize);
+            // c * dr - r * dc stays the same along a line, and is never
+            // further than width + height from zero
+            let (line, position) = if dr == 0 {
+                (row, c * dc)
+            } else {
+                ((c * dr - r * dc + offset) as usize, r * dr)
+            };
+            visit(line, position, row, column);
         }
     }
-    max
+}
+
+// Lines are numbered from 0 to twice the offset `sweep` adds.
+fn line_count(map: &Grid<u32>) -> usize {
+    2 * (map.height() + map.width()) + 1
+}
+
+// Marks every tree that is visible from outside the map. One sweep per
+// direction keeps the tallest tree seen so far on each line, so a tree is
+// visible from that side exactly if it is taller than that maximum and close
+// enough to the edge.
+fn visibility_grid(map: &Grid<u32>, rules: &Rules) -> Grid<bool> {
+    let mut visible = Grid::filled(map.width(), map.height(), false);
+    for direction in rules.directions.directions() {
+        let mut max: Vec<Option<u32>> = vec![None; line_count(map)];
+        let mut edges: Vec<Option<isize>> = vec![None; line_count(map)];
+        sweep(map, direction, |line, position, row, column| {
+            let edge = *edges[line].get_or_insert(position);
+            if max[line].is_none_or(|m| map[(row, column)] > m) && rules.within_limit(position - edge) {
+                visible[(row, column)] = true;
+                max[line] = Some(map[(row, column)]);
+            }
+        });
+    }
+    visible
+}
+
+// Scenic score of every tree. Walking each line from the edge, a stack keeps
+// the trees that could still block the view of a later tree, strictly
+// decreasing in height. Everything shorter than the current tree is popped,
+// the tree left on top is the one blocking its view, and the viewing distance
+// is the number of steps back to it, or to the edge if the stack ran empty.
+// Every tree is pushed and popped at most once per line. A limit only caps
+// the distance, the blocking tree is found the same way.
+fn scenic_grid(map: &Grid<u32>, rules: &Rules) -> Grid<u64> {
+    let mut scores = Grid::filled(map.width(), map.height(), 1);
+    for direction in rules.directions.directions() {
+        let mut stacks: Vec<Vec<(isize, u32)>> = vec![Vec::new(); line_count(map)];
+        let mut edges: Vec<Option<isize>> = vec![None; line_count(map)];
+        sweep(map, direction, |line, position, row, column| {
+            let height = map[(row, column)];
+            let stack = &mut stacks[line];
+            while stack.last().is_some_and(|&(_, h)| h < height) {
+                stack.pop();
+            }
+            let edge = *edges[line].get_or_insert(position);
+            let distance = match stack.last() {
+                Some(&(top, _)) => position - top,
+                None => position - edge,
+            };
+            scores[(row, column)] *= rules.limited(distance) as u64;
+            // a tree of the same height blocks everything behind it just as
+            // well, so only the nearer one needs to stay
+            if stack.last().is_some_and(|&(_, h)| h == height) {
+                stack.pop();
+            }
+            stack.push((position, height));
+        });
+    }
+    scores
+}
+
+fn solve_part1(input: &str) -> Result<u32, MapError> {
+    let map = parse_input(input)?;
+    Ok(visibility_grid(&map, &Rules::default()).iter().filter(|&&v| v).count() as u32)
+}
+
+// Position of the tree with the highest scenic score, the first one row by
+// row if several share it.
+fn best_tree(scores: &Grid<u64>) -> Option<(usize, usize)> {
+    scores.positions().fold(None, |best, position| match best {
+        Some(b) if scores[b] >= scores[position] => Some(b),
+        _ => Some(position),
+    })
+}
+
+fn solve_part2(input: &str) -> Result<u64, MapError> {
+    let map = parse_input(input)?;
+    let scores = scenic_grid(&map, &Rules::default());
+    Ok(best_tree(&scores).map_or(0, |best| scores[best]))
 }
 
 pub fn solve() {
-    println!("Solution for part 1 {}", solve_part1("src/eight/input.txt"));
-    println!("Solution for part 2 {}", solve_part2("src/eight/input.txt"));
+    match solve_part1("src/eight/input.txt") {
+        Ok(res) => println!("Solution for part 1 {}", res),
+        Err(e) => println!("Error in part 1: {}", e),
+    }
+    match solve_part2("src/eight/input.txt") {
+        Ok(res) => println!("Solution for part 2 {}", res),
+        Err(e) => println!("Error in part 2: {}", e),
+    }
+}
+
+const USAGE: &str = "\
+usage: advent_of_code_2022 8 [<output>]... [<rules>] [--layer visible|scenic] [--scale <n>] [--numbers] [<file>]
+rules:
+  --directions orthogonal|diagonal|all
+                      lines of sight to follow, orthogonal by default
+  --limit <n>         see at most n trees along a line of sight
+outputs:
+  --visible           print the forest with the trees visible from outside in green
+  --heatmap           print the forest coloured by scenic score
+  --ppm <file>        write the chosen layer as a PPM image
+  --png <file>        write the chosen layer as a PNG image
+Images show the scenic layer by default, with --scale pixels per tree (4).
+The best tree house spot is marked in red everywhere. Without outputs, the
+answers under the given rules are printed. Maps are rows of digits, or rows of
+whitespace separated heights; --numbers reads the latter even with one column.";
+
+enum Output {
+    Terminal(Layer),
+    Ppm(String),
+    Png(String),
+}
+
+// Solves or draws the puzzle input or the given map under the given rules,
+// with the outputs in the order given.
+pub fn run(options: &[String]) {
+    if options.is_empty() {
+        solve();
+        return;
+    }
+    let mut outputs: Vec<Output> = Vec::new();
+    let mut rules = Rules::default();
+    let mut format: Option<Format> = None;
+    let mut layer = Layer::Scenic;
+    let mut scale: usize = 4;
+    let mut path: Option<&str> = None;
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        match option.as_str() {
+            "--visible" => outputs.push(Output::Terminal(Layer::Visible)),
+            "--heatmap" => outputs.push(Output::Terminal(Layer::Scenic)),
+            "--ppm" => outputs.push(Output::Ppm(next_path(&mut iter, option).to_string())),
+            "--png" => outputs.push(Output::Png(next_path(&mut iter, option).to_string())),
+            "--layer" => {
+                layer = match iter.next().map(|s| s.as_str()) {
+                    Some("visible") => Layer::Visible,
+                    Some("scenic") => Layer::Scenic,
+                    _ => {
+                        println!("--layer needs \"visible\" or \"scenic\"");
+                        process::exit(1);
+                    }
+                }
+            },
+            "--directions" => {
+                rules.directions = match iter.next().map(|s| s.as_str()) {
+                    Some("orthogonal") => DirectionSet::Orthogonal,
+                    Some("diagonal") => DirectionSet::Diagonal,
+                    Some("all") => DirectionSet::All,
+                    _ => {
+                        println!("--directions needs \"orthogonal\", \"diagonal\" or \"all\"");
+                        process::exit(1);
+                    }
+                }
+            },
+            "--limit" => rules.limit = Some(next_number(&mut iter, option)),
+            "--numbers" => format = Some(Format::Numbers),
+            "--scale" => scale = next_number(&mut iter, option),
+            _ if path.is_none() && !option.starts_with("--") => path = Some(option),
+            _ => {
+                println!("Unexpected argument: \"{}\"", option);
+                process::exit(1);
+            }
+        }
+    }
+    if scale == 0 {
+        println!("{}", USAGE);
+        process::exit(1);
+    }
+    let path = path.unwrap_or("src/eight/input.txt");
+    let content = read_or_exit(path);
+    let map = match parse_map(&content, format.unwrap_or_else(|| Format::detect(&content))) {
+        Ok(map) => map,
+        Err(e) => {
+            println!("Error in {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    let scores = scenic_grid(&map, &rules);
+    if outputs.is_empty() {
+        println!("Solution for part 1 {}", visibility_grid(&map, &rules).iter().filter(|&&v| v).count());
+        println!("Solution for part 2 {}", best_tree(&scores).map_or(0, |best| scores[best]));
+        return;
+    }
+    for output in outputs {
+        let result = match output {
+            Output::Terminal(layer) => {
+                print!("{}", render_ansi(&map, &colors(&map, layer, &rules)));
+                Ok(())
+            },
+            Output::Ppm(target) => File::create(&target)
+                .and_then(|f| image::write_ppm(&upscale(&colors(&map, layer, &rules), scale), &mut BufWriter::new(f)))
+                .map_err(|e| (target, e)),
+            Output::Png(target) => File::create(&target)
+                .and_then(|f| image::write_png(&upscale(&colors(&map, layer, &rules), scale), &mut BufWriter::new(f)))
+                .map_err(|e| (target, e)),
+        };
+        if let Err((target, e)) = result {
+            println!("Error writing {}: {}", target, e);
+            process::exit(1);
+        }
+    }
+    if let Some((row, column)) = best_tree(&scores) {
+        println!("Best tree house at row {}, column {} with scenic score {}", row + 1, column + 1, scores[(row, column)]);
+    }
 }
 
 #[cfg(test)]
@@ -167,77 +380,261 @@ mod test {
     #[test]
     fn test_basic_visibility_1() {
         let expected: bool = true;
-        let map = vec![vec![1,1],vec![1,1]];
-        let output = is_visible(&map, 0, 0);
+        let map = Grid::from_rows(vec![vec![1,1],vec![1,1]]).unwrap();
+        let output = visibility_grid(&map, &Rules::default())[(0, 0)];
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_basic_visibility_2() {
         let expected: bool = true;
-        let map = vec![vec![1,1],vec![1,1]];
-        let output = is_visible(&map, 1, 1);
+        let map = Grid::from_rows(vec![vec![1,1],vec![1,1]]).unwrap();
+        let output = visibility_grid(&map, &Rules::default())[(1, 1)];
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_basic_visibility_3() {
         let expected: bool = true;
-        let map = vec![vec![1,2,3],vec![4,9,5],vec![6,7,8]];
-        let output = is_visible(&map, 1, 1);
+        let map = Grid::from_rows(vec![vec![1,2,3],vec![4,9,5],vec![6,7,8]]).unwrap();
+        let output = visibility_grid(&map, &Rules::default())[(1, 1)];
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_basic_visibility_not_visible() {
         let expected: bool = false;
-        let map = vec![vec![1,2,3],vec![4,1,5],vec![6,7,8]];
-        let output = is_visible(&map, 1, 1);
+        let map = Grid::from_rows(vec![vec![1,2,3],vec![4,1,5],vec![6,7,8]]).unwrap();
+        let output = visibility_grid(&map, &Rules::default())[(1, 1)];
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_top_right_one_not_visible() {
         let expected: bool = false;
-        let map = parse_input("src/eight/sample-input.txt");
-        let output = is_visible(&map, 1, 3);
+        let map = parse_input("src/eight/sample-input.txt").unwrap();
+        let output = visibility_grid(&map, &Rules::default())[(1, 3)];
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_top_left_five_visible() {
         let expected: bool = true;
-        let map = parse_input("src/eight/sample-input.txt");
-        let output = is_visible(&map, 1, 1);
+        let map = parse_input("src/eight/sample-input.txt").unwrap();
+        let output = visibility_grid(&map, &Rules::default())[(1, 1)];
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_top_mid_five_visible() {
         let expected: bool = true;
-        let map = parse_input("src/eight/sample-input.txt");
-        let output = is_visible(&map, 1, 2);
+        let map = parse_input("src/eight/sample-input.txt").unwrap();
+        let output = visibility_grid(&map, &Rules::default())[(1, 2)];
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_top_mid_five_count() {
-        let expected: u32 = 4;
-        let map = parse_input("src/eight/sample-input.txt");
-        let output = count_trees_in_view(&map, 1, 2);
+        let expected: u64 = 4;
+        let map = parse_input("src/eight/sample-input.txt").unwrap();
+        let output = scenic_grid(&map, &Rules::default())[(1, 2)];
         assert_eq!(expected, output);
     }
 
+    #[test]
+    fn test_sample_grids() {
+        let map = parse_input("src/eight/sample-input.txt").unwrap();
+        let visible = visibility_grid(&map, &Rules::default()).map(|&v| if v { '#' } else { '.' });
+        assert_eq!("#####\n###.#\n##.##\n#.#.#\n#####\n", visible.to_string());
+        let expected_scores = Grid::from_rows(vec![
+            vec![0, 0, 0, 0, 0],
+            vec![0, 1, 4, 1, 0],
+            vec![0, 6, 1, 2, 0],
+            vec![0, 1, 8, 3, 0],
+            vec![0, 0, 0, 0, 0],
+        ]).unwrap();
+        assert_eq!(expected_scores, scenic_grid(&map, &Rules::default()));
+    }
+
+    fn transpose<T: Copy>(grid: &Grid<T>) -> Grid<T> {
+        Grid::from_rows((0..grid.width()).map(|column| grid.column(column).copied().collect()).collect()).unwrap()
+    }
+
+    // Forests wider than they are tall, and the other way around, number the
+    // lines of the vertical sweeps below zero without the offset.
+    #[test]
+    fn test_rectangular_grids() {
+        let rows = vec![vec![3, 0, 3, 7, 3], vec![2, 5, 5, 1, 2], vec![6, 5, 3, 3, 2]];
+        let wide = Grid::from_rows(rows.clone()).unwrap();
+        let tall = transpose(&wide);
+        let visible = Grid::from_rows(vec![vec![true; 5], vec![true, true, true, false, true], vec![true; 5]]).unwrap();
+        let scores = Grid::from_rows(vec![vec![0; 5], vec![0, 1, 2, 1, 0], vec![0; 5]]).unwrap();
+        assert_eq!(visible, visibility_grid(&wide, &Rules::default()));
+        assert_eq!(scores, scenic_grid(&wide, &Rules::default()));
+        assert_eq!(transpose(&visible), visibility_grid(&tall, &Rules::default()));
+        assert_eq!(transpose(&scores), scenic_grid(&tall, &Rules::default()));
+        let flat = Grid::from_rows(rows[..2].to_vec()).unwrap();
+        assert_eq!(Grid::filled(5, 2, true), visibility_grid(&flat, &Rules::default()));
+    }
+
+    // Timing for big forests, run with
+    // `cargo test --release eight::test::bench_large_forest -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_large_forest() {
+        use std::time::Instant;
+
+        let size = 2000;
+        // a simple linear congruential generator keeps the forest reproducible
+        let mut seed: u64 = 8;
+        let map = Grid::filled(size, size, 0).map(|_| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 33) % 10) as u32
+        });
+        let start = Instant::now();
+        let visible = visibility_grid(&map, &Rules::default()).iter().filter(|&&v| v).count();
+        let visibility_time = start.elapsed();
+        let start = Instant::now();
+        let best = scenic_grid(&map, &Rules::default()).iter().copied().max().unwrap();
+        let scenic_time = start.elapsed();
+        println!("{}x{}: {} visible in {:?}, best score {} in {:?}", size, size, visible, visibility_time, best, scenic_time);
+    }
+
+    // A limit of one hides everything but the edge, and only the diagonals
+    // reach the middle of this forest without passing a taller tree.
+    #[test]
+    fn test_rules() {
+        let map = Grid::from_rows(vec![vec![1,5,1],vec![5,3,5],vec![1,5,1]]).unwrap();
+        let diagonal = Rules { directions: DirectionSet::Diagonal, limit: None };
+        assert!(!visibility_grid(&map, &Rules::default())[(1, 1)]);
+        assert!(visibility_grid(&map, &diagonal)[(1, 1)]);
+        let limited = Rules { directions: DirectionSet::All, limit: Some(1) };
+        let visible = visibility_grid(&map, &limited).map(|&v| if v { '#' } else { '.' });
+        assert_eq!("###\n#.#\n###\n", visible.to_string());
+        let map = Grid::from_rows(vec![vec![9,9,9,9,9,9],vec![0,5,1,1,1,9],vec![9,9,9,9,9,9]]).unwrap();
+        let limited = Rules { directions: DirectionSet::Orthogonal, limit: Some(2) };
+        assert_eq!(4, scenic_grid(&map, &Rules::default())[(1, 1)]);
+        assert_eq!(2, scenic_grid(&map, &limited)[(1, 1)]);
+    }
+
+    // Whether a single tree can be seen from outside, following its lines of
+    // sight directly.
+    fn ray_visible(map: &Grid<u32>, rules: &Rules, (row, column): (usize, usize)) -> bool {
+        rules.directions.directions().iter().any(|&direction| {
+            let mut trees_in_front = 0;
+            let all_lower = map.ray(row, column, direction).all(|position| {
+                trees_in_front += 1;
+                map[position] < map[(row, column)]
+            });
+            all_lower && rules.within_limit(trees_in_front)
+        })
+    }
+
+    // Scenic score of a single tree, following its lines of sight directly.
+    fn ray_score(map: &Grid<u32>, rules: &Rules, (row, column): (usize, usize)) -> u64 {
+        rules.directions.directions().iter().map(|&direction| {
+            let mut count = 0;
+            for position in map.ray(row, column, direction).take(rules.limit.unwrap_or(usize::MAX)) {
+                count += 1;
+                if map[position] >= map[(row, column)] {
+                    break;
+                }
+            }
+            count
+        }).product()
+    }
+
+    // The sweeps have to agree with following every line of sight of every
+    // tree, for all direction sets and limits and on forests that are not
+    // square.
+    #[test]
+    fn test_sweeps_match_rays() {
+        let mut seed: u64 = 42;
+        for (height, width) in [(1, 1), (1, 6), (5, 2), (7, 11), (12, 9)] {
+            let map = Grid::filled(width, height, 0).map(|_| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                ((seed >> 33) % 6) as u32
+            });
+            for directions in [DirectionSet::Orthogonal, DirectionSet::Diagonal, DirectionSet::All] {
+                for limit in [None, Some(0), Some(1), Some(3)] {
+                    let rules = Rules { directions, limit };
+                    let visible = visibility_grid(&map, &rules);
+                    let scores = scenic_grid(&map, &rules);
+                    for (row, column) in map.positions() {
+                        assert_eq!(ray_visible(&map, &rules, (row, column)), visible[(row, column)]);
+                        assert_eq!(ray_score(&map, &rules, (row, column)), scores[(row, column)]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_map() {
+        let expected = Grid::from_rows(vec![vec![3, 0, 7], vec![1, 2, 5]]).unwrap();
+        assert_eq!(Ok(expected), parse_map("307\r\n125\r\n\n", Format::Digits));
+        let expected = Grid::from_rows(vec![vec![12, 0, 7], vec![1, 200, 5]]).unwrap();
+        let content = "12 0  7\n\n1\t200 5\n";
+        assert_eq!(Format::Numbers, Format::detect(content));
+        assert_eq!(Ok(expected), parse_map(content, Format::Numbers));
+        assert_eq!(Format::Digits, Format::detect("12\n34\n"));
+        let column = Grid::from_rows(vec![vec![12], vec![34]]).unwrap();
+        assert_eq!(Ok(column), parse_map("12\n34\n", Format::Numbers));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let expected = Err(MapError::Ragged { line: 4, expected: 3, found: 2 });
+        assert_eq!(expected, parse_map("123\n456\n\n78\n", Format::Digits));
+        let expected = Err(MapError::InvalidHeight { line: 2, column: 2, token: "x".to_string() });
+        assert_eq!(expected, parse_map("123\n4x6\n", Format::Digits));
+        let expected = Err(MapError::InvalidHeight { line: 1, column: 3, token: "-1".to_string() });
+        assert_eq!(expected, parse_map("1 2 -1\n", Format::Numbers));
+        let message = MapError::Ragged { line: 4, expected: 3, found: 2 }.to_string();
+        assert_eq!("line 4: expected 3 trees like the first row, found 2", message);
+    }
+
+    // Rows and columns differ in length here, so mixing them up would go out
+    // of bounds or look at the wrong trees.
+    #[test]
+    fn test_rectangular_forest() {
+        let map = parse_map("30373255\n25512536\n", Format::Digits).unwrap();
+        let visible = visibility_grid(&map, &Rules::default()).map(|&v| if v { '#' } else { '.' });
+        assert_eq!("########\n########\n", visible.to_string());
+        let map = parse_map("3 0 3 7 3\n2 5 5 1 2\n6 5 3 3 2\n3 3 5 4 9\n", Format::Numbers).unwrap();
+        assert_eq!(4, map.height());
+        assert_eq!(5, map.width());
+        let visible = visibility_grid(&map, &Rules::default());
+        assert!(!visible[(1, 3)]);
+        assert!(visible[(1, 2)]);
+        assert_eq!(3, scenic_grid(&map, &Rules::default())[(2, 1)]);
+    }
+
+    #[test]
+    fn test_best_tree() {
+        let map = parse_input("src/eight/sample-input.txt").unwrap();
+        assert_eq!(Some((3, 2)), best_tree(&scenic_grid(&map, &Rules::default())));
+        let ties = Grid::from_rows(vec![vec![4, 4], vec![4, 4]]).unwrap();
+        assert_eq!(Some((0, 0)), best_tree(&ties));
+    }
+
+    #[test]
+    fn test_empty_forest() {
+        let map = Grid::from_rows(Vec::<Vec<u32>>::new()).unwrap();
+        assert_eq!(0, visibility_grid(&map, &Rules::default()).iter().filter(|&&v| v).count());
+        assert_eq!(None, scenic_grid(&map, &Rules::default()).iter().max());
+    }
+
     #[test]
     fn test_sample_part1() {
-        let expected: u32 = 21;
+        let expected = Ok(21);
         let output = solve_part1("src/eight/sample-input.txt");
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_sample_part2() {
-        let expected: u32 = 8;
+        let expected = Ok(8);
         let output = solve_part2("src/eight/sample-input.txt");
         assert_eq!(expected, output);
     }
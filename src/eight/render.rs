@@ -0,0 +1,126 @@
+use crate::grid::Grid;
+use crate::image::Rgb;
+
+use super::{best_tree, scenic_grid, visibility_grid, Rules};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layer {
+    Visible,
+    Scenic,
+}
+
+// The best tree house spot, in a colour neither layer uses otherwise.
+const BEST: Rgb = [230, 30, 30];
+
+// Colour stops of the scenic score heatmap, from the lowest score to the
+// highest.
+const HEAT: [Rgb; 5] = [[0, 0, 0], [20, 30, 170], [0, 170, 190], [240, 220, 0], [255, 255, 255]];
+
+// Where `value` lies between 0 and `max`, from 0.0 to 1.0.
+fn fraction(value: f64, max: f64) -> f64 {
+    if max > 0.0 { value / max } else { 0.0 }
+}
+
+fn heat(t: f64) -> Rgb {
+    let scaled = t.clamp(0.0, 1.0) * (HEAT.len() - 1) as f64;
+    let index = (scaled as usize).min(HEAT.len() - 2);
+    let local = scaled - index as f64;
+    let (from, to) = (HEAT[index], HEAT[index + 1]);
+    let mut color = [0; 3];
+    for i in 0..3 {
+        color[i] = (from[i] as f64 + (to[i] as f64 - from[i] as f64) * local).round() as u8;
+    }
+    color
+}
+
+// One colour per tree. The visibility layer shows visible trees in green and
+// hidden ones in grey, lighter the taller they are. Scenic scores span several
+// orders of magnitude, so the heatmap uses a logarithmic scale.
+pub fn colors(map: &Grid<u32>, layer: Layer, rules: &Rules) -> Grid<Rgb> {
+    let scores = scenic_grid(map, rules);
+    let mut colors = match layer {
+        Layer::Visible => {
+            let visible = visibility_grid(map, rules);
+            let max_height = map.iter().copied().max().unwrap_or(0) as f64;
+            let mut colors = Grid::filled(map.width(), map.height(), [0, 0, 0]);
+            for position in map.positions() {
+                let t = fraction(map[position] as f64, max_height);
+                colors[position] = if visible[position] {
+                    [0, (70.0 + 185.0 * t) as u8, 0]
+                } else {
+                    let grey = (30.0 + 90.0 * t) as u8;
+                    [grey, grey, grey]
+                };
+            }
+            colors
+        },
+        Layer::Scenic => {
+            let max_score = (scores.iter().copied().max().unwrap_or(0) as f64).ln_1p();
+            scores.map(|&score| heat(fraction((score as f64).ln_1p(), max_score)))
+        },
+    };
+    if let Some(best) = best_tree(&scores) {
+        colors[best] = BEST;
+    }
+    colors
+}
+
+// The forest as it is drawn in the input, with every tree on a background of
+// its colour, using 24 bit ANSI escape codes. Heights above 9 are written
+// right aligned with a space in front, like the numeric map format.
+pub fn render_ansi(map: &Grid<u32>, colors: &Grid<Rgb>) -> String {
+    let digits = map.iter().copied().max().unwrap_or(0).to_string().len();
+    let width = if digits > 1 { digits + 1 } else { 1 };
+    let mut output = String::new();
+    for row in 0..map.height() {
+        for column in 0..map.width() {
+            let [r, g, b] = colors[(row, column)];
+            // dark text on light backgrounds and the other way around
+            let luma = 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+            let foreground = if luma > 128_000 { 30 } else { 97 };
+            let tree = map[(row, column)];
+            output.push_str(&format!("\x1b[{};48;2;{};{};{}m{:>width$}", foreground, r, g, b, tree, width = width));
+        }
+        output.push_str("\x1b[0m\n");
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::parse_input;
+
+    #[test]
+    fn test_heat() {
+        assert_eq!(HEAT[0], heat(0.0));
+        assert_eq!(HEAT[2], heat(0.5));
+        assert_eq!(HEAT[4], heat(1.0));
+        assert_eq!([10, 15, 85], heat(0.125));
+    }
+
+    #[test]
+    fn test_sample_layers() {
+        let map = parse_input("src/eight/sample-input.txt").unwrap();
+        let visible = colors(&map, Layer::Visible, &Rules::default());
+        assert_eq!(BEST, visible[(3, 2)]);
+        // the 1 in the second row is hidden
+        assert_eq!([40, 40, 40], visible[(1, 3)]);
+        assert_eq!([0, 255, 0], visible[(4, 4)].map(|c| if c > 0 { 255 } else { 0 }));
+        let scenic = colors(&map, Layer::Scenic, &Rules::default());
+        assert_eq!(BEST, scenic[(3, 2)]);
+        assert_eq!(HEAT[0], scenic[(0, 0)]);
+        assert_ne!(HEAT[0], scenic[(2, 1)]);
+    }
+
+    #[test]
+    fn test_render_ansi() {
+        let map = Grid::from_rows(vec![vec![1, 12]]).unwrap();
+        let colors = Grid::from_rows(vec![vec![[0, 0, 0], [255, 255, 255]]]).unwrap();
+        let expected = "\x1b[97;48;2;0;0;0m  1\x1b[30;48;2;255;255;255m 12\x1b[0m\n";
+        assert_eq!(expected, render_ansi(&map, &colors));
+        let map = Grid::from_rows(vec![vec![1, 9]]).unwrap();
+        let expected = "\x1b[97;48;2;0;0;0m1\x1b[30;48;2;255;255;255m9\x1b[0m\n";
+        assert_eq!(expected, render_ansi(&map, &colors));
+    }
+}
@@ -1,9 +1,98 @@
 use std::fmt;
 use std::fs;
+use std::io;
+use std::process;
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use crate::cli::next_path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Crane {
+    CrateMover9000,
+    CrateMover9001,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum MoveError {
+    Malformed { line: usize, content: String },
+    InvalidStack { line: usize, stack: usize, amount_stacks: usize },
+    NotEnoughCrates { line: usize, stack: usize, requested: usize, available: usize },
+    // undoing the move on `line` needs more crates on its destination than
+    // there are
+    CannotUndo { line: usize, from: usize, to: usize, requested: usize, available: usize },
+    EmptyStack { stack: usize },
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::Malformed { line, content } => {
+                write!(f, "line {}: malformed instruction \"{}\"", line, content)
+            },
+            MoveError::InvalidStack { line, stack, amount_stacks } => {
+                write!(f, "line {}: stack {} does not exist (stacks are 1 to {})", line, stack, amount_stacks)
+            },
+            MoveError::NotEnoughCrates { line, stack, requested, available } => {
+                write!(f, "line {}: cannot move {} crates from stack {} holding {}", line, requested, stack, available)
+            },
+            MoveError::CannotUndo { line, from, to, requested, available } => {
+                write!(f, "line {}: cannot undo moving {} crates from stack {} to stack {} holding {}",
+                    line, requested, from, to, available)
+            },
+            MoveError::EmptyStack { stack } => {
+                write!(f, "stack {} is empty at the end", stack)
+            },
+        }
+    }
+}
+
+// Problems with an input file as a whole, before any crate is moved.
 #[derive(Debug)]
+enum InputError {
+    Read(io::Error),
+    // no blank line between the drawing and the moves
+    MissingMoves,
+    // the last line of the drawing must number the stacks 1, 2, 3 and so on
+    StackNumbers { line: String },
+    // a crate further right than the last numbered stack
+    CrateOutsideStacks { line: usize, amount_stacks: usize },
+    Moves(MoveError),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputError::Read(e) => write!(f, "{}", e),
+            InputError::MissingMoves => write!(f, "no blank line between the drawing and the moves"),
+            InputError::StackNumbers { line } => {
+                write!(f, "the drawing ends in \"{}\" instead of the stack numbers", line)
+            },
+            InputError::CrateOutsideStacks { line, amount_stacks } => {
+                write!(f, "line {}: crate beyond the last of {} stacks", line, amount_stacks)
+            },
+            InputError::Moves(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Move {
+    pub amount: usize,
+    pub from: usize,
+    pub to: usize,
+    pub line: usize,
+}
+
+impl Move {
+    // Undoing a move is the same crane lifting the same crates back, so both
+    // models are inverted by swapping source and destination.
+    fn reversed(&self) -> Move {
+        Move { from: self.to, to: self.from, ..*self }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct CrateStack {
     crates: Vec<char>,
 }
@@ -21,18 +110,29 @@ impl CrateStack {
     fn new() -> CrateStack {
         CrateStack { crates : vec![], }
     }
-    fn pop(&mut self) -> char {
-        return self.crates.pop().unwrap();
+    fn pop(&mut self) -> Option<char> {
+        self.crates.pop()
     }
     fn push(&mut self, c: char) {
         self.crates.push(c);
     }
-    fn last(&self) -> char {
-        return *self.crates.last().unwrap();
+    fn last(&self) -> Option<char> {
+        self.crates.last().copied()
+    }
+    fn len(&self) -> usize {
+        self.crates.len()
+    }
+    // Removes the top `amount` crates in one go, bottom-most first.
+    fn take(&mut self, amount: usize) -> Vec<char> {
+        let at = self.crates.len() - amount;
+        self.crates.split_off(at)
+    }
+    fn put(&mut self, crates: Vec<char>) {
+        self.crates.extend(crates);
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct CrateStorage {
     amount_stacks: usize,
     crate_stacks: Vec<CrateStack>,
@@ -40,9 +140,9 @@ struct CrateStorage {
 
 impl fmt::Display for CrateStorage {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Amount Stacks: {}\n", self.amount_stacks)?;
+        writeln!(f, "Amount Stacks: {}", self.amount_stacks)?;
         for (i, stack) in self.crate_stacks.iter().enumerate() {
-            write!(f, "{}: {}\n", i, stack)?;
+            writeln!(f, "{}: {}", i, stack)?;
         }
         Ok(())
     }
@@ -56,155 +156,533 @@ impl CrateStorage {
         }
         CrateStorage {
             amount_stacks: size,
-            crate_stacks: crate_stacks,
+            crate_stacks,
         }
     }
     #[allow(dead_code)]
     fn get_amount(self) -> usize {
-        return self.amount_stacks;
+        self.amount_stacks
     }
     #[allow(dead_code)]
-    fn pop(&mut self, i: usize) -> char {
-        return self.crate_stacks[i-1].pop();
+    fn pop(&mut self, i: usize) -> Option<char> {
+        self.crate_stacks.get_mut(i.checked_sub(1)?)?.pop()
     }
 
     fn push(&mut self, c: char, i: usize) {
         self.crate_stacks[i].push(c);
     }
+    fn check_stack(&self, stack: usize, line: usize) -> Result<(), MoveError> {
+        if stack == 0 || stack > self.amount_stacks {
+            return Err(MoveError::InvalidStack { line, stack, amount_stacks: self.amount_stacks });
+        }
+        Ok(())
+    }
+    // Checks a move against the current state without touching any stack, so
+    // a rejected move leaves the storage as it was.
+    fn validate(&self, m: &Move) -> Result<(), MoveError> {
+        self.check_stack(m.from, m.line)?;
+        self.check_stack(m.to, m.line)?;
+        let available = self.crate_stacks[m.from-1].len();
+        if m.amount > available {
+            return Err(MoveError::NotEnoughCrates {
+                line: m.line,
+                stack: m.from,
+                requested: m.amount,
+                available,
+            });
+        }
+        Ok(())
+    }
+    // The CrateMover 9000 lifts one crate at a time, which ends up reversing
+    // the moved slice.
     fn move_amount_from_to(&mut self, amount: usize, from: usize, to: usize) {
-        for _i in 0..amount {
-            let element: char = self.crate_stacks[from-1].pop();
-            self.crate_stacks[to-1].push(element);
+        if from == to {
+            return;
         }
+        let mut moved = self.crate_stacks[from-1].take(amount);
+        moved.reverse();
+        self.crate_stacks[to-1].put(moved);
     }
     fn move_amount_from_to_keeping_order(&mut self, amount: usize, from: usize, to: usize) {
-        let mut temporary_stack: Vec<char> = Vec::new();
-        for _i in 0..amount {
-            temporary_stack.push(self.crate_stacks[from-1].pop());
+        if from == to {
+            return;
         }
-        for _i in 0..amount {
-            self.crate_stacks[to-1].push(temporary_stack.pop().unwrap());
+        let moved = self.crate_stacks[from-1].take(amount);
+        self.crate_stacks[to-1].put(moved);
+    }
+    fn apply(&mut self, m: &Move, crane: Crane) -> Result<(), MoveError> {
+        self.validate(m)?;
+        match crane {
+            Crane::CrateMover9000 => self.move_amount_from_to(m.amount, m.from, m.to),
+            Crane::CrateMover9001 => self.move_amount_from_to_keeping_order(m.amount, m.from, m.to),
+        }
+        Ok(())
+    }
+    // Errors name the stacks the way the move itself does, not the way it is
+    // reversed.
+    fn undo(&mut self, m: &Move, crane: Crane) -> Result<(), MoveError> {
+        self.apply(&m.reversed(), crane).map_err(|e| match e {
+            MoveError::NotEnoughCrates { line, requested, available, .. } => {
+                MoveError::CannotUndo { line, from: m.from, to: m.to, requested, available }
+            },
+            e => e,
+        })
+    }
+    // Renders the storage in the puzzle's drawing format, which
+    // `parse_configuration` reads back.
+    fn drawing(&self) -> String {
+        let height = self.crate_stacks.iter().map(|s| s.len()).max().unwrap_or(0);
+        let mut lines: Vec<String> = Vec::new();
+        for level in (0..height).rev() {
+            let row: Vec<String> = self.crate_stacks.iter().map(|s| match s.crates.get(level) {
+                Some(c) => format!("[{}]", c),
+                None => "   ".to_string(),
+            }).collect();
+            lines.push(row.join(" "));
         }
+        let numbers: Vec<String> = (1..=self.amount_stacks).map(|i| format!(" {} ", i)).collect();
+        lines.push(numbers.join(" "));
+        lines.join("\n")
     }
-    fn part1(&self) -> String {
+    // Top crate of every stack. An empty stack is an error unless a
+    // placeholder is given, which then stands in for the missing crate.
+    fn top_crates(&self, placeholder: Option<char>) -> Result<String, MoveError> {
         let mut result: String = String::new();
-        for element in &self.crate_stacks {
-            result.push(element.last());
+        for (i, element) in self.crate_stacks.iter().enumerate() {
+            match element.last().or(placeholder) {
+                Some(c) => result.push(c),
+                None => return Err(MoveError::EmptyStack { stack: i+1 }),
+            }
         }
-        result
+        Ok(result)
     }
 }
 
 
-fn parse_input(input_file: &str) -> Vec<String> {
-    let content = fs::read_to_string(input_file).unwrap();
-    let binding = content.clone();
-    let parts: Vec<&str> = binding.split("\n\n").collect::<Vec<&str>>();
-    let first_part = String::from(parts[0]);
-    let second_part = String::from(parts[1]);
-    vec![first_part, second_part]
+fn parse_input(input_file: &str) -> Result<Vec<String>, InputError> {
+    let content = fs::read_to_string(input_file).map_err(InputError::Read)?;
+    let (first_part, second_part) = content.split_once("\n\n").ok_or(InputError::MissingMoves)?;
+    Ok(vec![String::from(first_part), String::from(second_part)])
 }
 
-fn solve_part1(input: &str) -> String {
-    let parsed_input = parse_input(input);
-    let part1 = &parsed_input[0];
-    let part2 = &parsed_input[1];
-
-    let initial_configuration: Vec<&str> = part1.split("\n").collect();
-    let instructions: Vec<&str> = part2.split("\n").collect();
+// Rows of crates may stop after their last crate, so the number of stacks
+// comes from the line numbering them.
+fn parse_configuration(part: &str) -> Result<CrateStorage, InputError> {
+    let initial_configuration: Vec<&str> = part.split('\n').collect();
 
-    let amount_lines = initial_configuration.len() as usize;
-    let amount_characters_in_line = initial_configuration[0].len() as usize;
-    let amount_stacks = ( amount_characters_in_line + 1 ) / 4 as usize;
+    let amount_lines = initial_configuration.len();
+    let number_line = initial_configuration[amount_lines-1];
+    let numbers: Vec<&str> = number_line.split_whitespace().collect();
+    let numbered = numbers.iter().enumerate().all(|(i, n)| n.parse::<usize>() == Ok(i + 1));
+    if numbers.is_empty() || !numbered {
+        return Err(InputError::StackNumbers { line: number_line.to_string() });
+    }
+    let amount_stacks = numbers.len();
     let mut crate_storage: CrateStorage = CrateStorage::new(amount_stacks);
-    //println!("crate_storage: \n {}", crate_storage);
-    for element in initial_configuration[0..amount_lines-1].iter().rev() {
+    for (line, element) in initial_configuration[0..amount_lines-1].iter().enumerate().rev() {
         let result: Vec<_> = element.chars().skip(1).step_by(4).collect::<Vec<char>>();
         for (i, e) in result.iter().enumerate() {
-            if *e != ' ' {
-                crate_storage.push(*e, i);
+            if *e == ' ' {
+                continue;
             }
+            if i >= amount_stacks {
+                return Err(InputError::CrateOutsideStacks { line: line + 1, amount_stacks });
+            }
+            crate_storage.push(*e, i);
         }
     }
-    //println!("Filled Crate Storage: {}", crate_storage);
+    Ok(crate_storage)
+}
+
+// `first_line` is the line number of the first instruction in the input
+// file, so errors can point at the offending line.
+fn parse_moves(part: &str, first_line: usize) -> Result<Vec<Move>, MoveError> {
     lazy_static! {
         static ref RE: Regex = Regex::new(r"^move (\d+) from (\d+) to (\d+)$").unwrap();
     }
-    for line in instructions {
-        //println!("Line: {}", line);
-        // move around
-        for cap in RE.captures_iter(line) {
-            let amount: usize = cap[1].parse::<usize>().unwrap();
-            let from: usize = cap[2].parse::<usize>().unwrap();
-            let to: usize = cap[3].parse::<usize>().unwrap();
-            crate_storage.move_amount_from_to(amount, from, to);
+    let mut moves: Vec<Move> = Vec::new();
+    for (i, line) in part.lines().enumerate() {
+        if line.is_empty() {
+            continue;
         }
-        //println!("{}", crate_storage);
+        let malformed = || MoveError::Malformed { line: first_line + i, content: line.to_string() };
+        let cap = RE.captures(line).ok_or_else(malformed)?;
+        let parse = |n: usize| cap[n].parse::<usize>().map_err(|_| malformed());
+        moves.push(Move {
+            amount: parse(1)?,
+            from: parse(2)?,
+            to: parse(3)?,
+            line: first_line + i,
+        });
+    }
+    Ok(moves)
+}
+
+fn simulate(start: &CrateStorage, moves: &[Move], crane: Crane) -> Result<CrateStorage, MoveError> {
+    let mut crate_storage = start.clone();
+    for m in moves {
+        crate_storage.apply(m, crane)?;
     }
-    let res = String::from(crate_storage.part1());
-    res
+    Ok(crate_storage)
 }
 
-fn solve_part2(input: &str) -> String {
-    let parsed_input = parse_input(input);
-    let part1 = &parsed_input[0];
-    let part2 = &parsed_input[1];
+// Runs the moves backwards from a final state to recover the arrangement the
+// crane started with.
+fn reconstruct(final_state: &CrateStorage, moves: &[Move], crane: Crane) -> Result<CrateStorage, MoveError> {
+    let mut crate_storage = final_state.clone();
+    for m in moves.iter().rev() {
+        crate_storage.undo(m, crane)?;
+    }
+    Ok(crate_storage)
+}
 
-    let initial_configuration: Vec<&str> = part1.split("\n").collect();
-    let instructions: Vec<&str> = part2.split("\n").collect();
+// Whether the moves lead from the claimed start to the final state. A move
+// list that cannot be undone at all is never consistent.
+fn is_consistent(start: &CrateStorage, moves: &[Move], final_state: &CrateStorage, crane: Crane) -> bool {
+    match reconstruct(final_state, moves, crane) {
+        Ok(reconstructed) => reconstructed == *start,
+        Err(_) => false,
+    }
+}
 
-    let amount_lines = initial_configuration.len() as usize;
-    let amount_characters_in_line = initial_configuration[0].len() as usize;
-    let amount_stacks = ( amount_characters_in_line + 1 ) / 4 as usize;
-    let mut crate_storage: CrateStorage = CrateStorage::new(amount_stacks);
-    //println!("crate_storage: \n {}", crate_storage);
-    for element in initial_configuration[0..amount_lines-1].iter().rev() {
-        let result: Vec<_> = element.chars().skip(1).step_by(4).collect::<Vec<char>>();
-        for (i, e) in result.iter().enumerate() {
-            if *e != ' ' {
-                crate_storage.push(*e, i);
-            }
+// The drawing and the moves of an input file.
+fn parse_file(input: &str) -> Result<(CrateStorage, Vec<Move>), InputError> {
+    let parsed_input = parse_input(input)?;
+    let crate_storage = parse_configuration(&parsed_input[0])?;
+    // the drawing, then the blank separator line
+    let first_line = parsed_input[0].lines().count() + 2;
+    let moves = parse_moves(&parsed_input[1], first_line).map_err(InputError::Moves)?;
+    Ok((crate_storage, moves))
+}
+
+fn solve_with(start: &CrateStorage, moves: &[Move], crane: Crane, placeholder: Option<char>) -> Result<String, MoveError> {
+    simulate(start, moves, crane)?.top_crates(placeholder)
+}
+
+fn solve_part1(start: &CrateStorage, moves: &[Move], placeholder: Option<char>) -> Result<String, MoveError> {
+    solve_with(start, moves, Crane::CrateMover9000, placeholder)
+}
+
+fn solve_part2(start: &CrateStorage, moves: &[Move], placeholder: Option<char>) -> Result<String, MoveError> {
+    solve_with(start, moves, Crane::CrateMover9001, placeholder)
+}
+
+// For the command line, where a file that does not parse ends the program.
+fn parse_or_exit(input: &str) -> (CrateStorage, Vec<Move>) {
+    match parse_file(input) {
+        Ok(parsed) => parsed,
+        Err(InputError::Read(e)) => {
+            println!("Error reading {}: {}", input, e);
+            process::exit(1);
+        },
+        Err(e) => {
+            println!("Error in {}: {}", input, e);
+            process::exit(1);
         }
     }
-    //println!("Filled Crate Storage: {}", crate_storage);
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"^move (\d+) from (\d+) to (\d+)$").unwrap();
+}
+
+fn report(input: &str, placeholder: Option<char>) {
+    let (start, moves) = parse_or_exit(input);
+    match solve_part1(&start, &moves, placeholder) {
+        Ok(res) => println!("Solution for part 1 {}", res),
+        Err(e) => println!("Error in part 1: {}", e),
     }
-    for line in instructions {
-        //println!("Line: {}", line);
-        // move around
-        for cap in RE.captures_iter(line) {
-            let amount: usize = cap[1].parse::<usize>().unwrap();
-            let from: usize = cap[2].parse::<usize>().unwrap();
-            let to: usize = cap[3].parse::<usize>().unwrap();
-            crate_storage.move_amount_from_to_keeping_order(amount, from, to);
-        }
-        //println!("{}", crate_storage);
+    match solve_part2(&start, &moves, placeholder) {
+        Ok(res) => println!("Solution for part 2 {}", res),
+        Err(e) => println!("Error in part 2: {}", e),
     }
-    let res = String::from(crate_storage.part1());
-    res
 }
 
 pub fn solve() {
-    println!("Solution for part 1 {}", solve_part1("src/five/input.txt"));
-    println!("Solution for part 2 {}", solve_part2("src/five/input.txt"));
+    report("src/five/input.txt", None);
+}
+
+// Prints the arrangement each crane must have started from to end up with
+// the drawing in `input` after its moves.
+fn report_reconstruction(input: &str) {
+    let (final_state, moves) = parse_or_exit(input);
+    for crane in [Crane::CrateMover9000, Crane::CrateMover9001] {
+        match reconstruct(&final_state, &moves, crane) {
+            Ok(start) => println!("Start for the {:?}:\n{}", crane, start.drawing()),
+            Err(e) => println!("Error for the {:?}: {}", crane, e),
+        }
+    }
+}
+
+// Prints for each crane whether the moves in `input` lead from its drawing
+// to the drawing in `final_file`.
+fn report_consistency(input: &str, final_file: &str) {
+    let (start, moves) = parse_or_exit(input);
+    let content = match fs::read_to_string(final_file) {
+        Ok(content) => content,
+        Err(e) => {
+            println!("Error reading {}: {}", final_file, e);
+            process::exit(1);
+        }
+    };
+    // only the drawing counts, moves after it are ignored
+    let final_state = match parse_configuration(content.split("\n\n").next().unwrap_or("").trim_end_matches('\n')) {
+        Ok(final_state) => final_state,
+        Err(e) => {
+            println!("Error in {}: {}", final_file, e);
+            process::exit(1);
+        }
+    };
+    for crane in [Crane::CrateMover9000, Crane::CrateMover9001] {
+        let verdict = if is_consistent(&start, &moves, &final_state, crane) { "lead" } else { "do not lead" };
+        println!("With the {:?} the moves {} to the final arrangement", crane, verdict);
+    }
+}
+
+const USAGE: &str = "\
+usage: advent_of_code_2022 5 [--placeholder <char> | --reconstruct | --check <final>] [<file>]
+Moves the crates in <file>, or the puzzle input, with both cranes and prints
+the top crates.
+  --placeholder <char>
+                      stand in for the top of an empty stack instead of
+                      failing
+  --reconstruct       take the drawing in <file> as the arrangement after the
+                      moves and print the one before them
+  --check <final>     tell whether the moves in <file> lead from its drawing to
+                      the drawing in <final>";
+
+// Solves, reconstructs or checks the puzzle input or the given file.
+pub fn run(options: &[String]) {
+    if options.is_empty() {
+        solve();
+        return;
+    }
+    let mut placeholder: Option<char> = None;
+    let mut reconstruction = false;
+    let mut final_file: Option<&str> = None;
+    let mut path: Option<&str> = None;
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        match option.as_str() {
+            "--placeholder" => {
+                let mut chars = iter.next().map(|s| s.chars());
+                placeholder = match chars.as_mut().map(|c| (c.next(), c.next())) {
+                    Some((Some(c), None)) => Some(c),
+                    _ => {
+                        println!("--placeholder needs a single character");
+                        process::exit(1);
+                    }
+                }
+            },
+            "--reconstruct" => reconstruction = true,
+            "--check" => final_file = Some(next_path(&mut iter, option)),
+            _ if path.is_none() && !option.starts_with("--") => path = Some(option),
+            _ => {
+                println!("Unexpected argument: \"{}\"", option);
+                println!("{}", USAGE);
+                process::exit(1);
+            }
+        }
+    }
+    let path = path.unwrap_or("src/five/input.txt");
+    match (placeholder, reconstruction, final_file) {
+        (_, false, None) => report(path, placeholder),
+        (None, true, None) => report_reconstruction(path),
+        (None, false, Some(final_file)) => report_consistency(path, final_file),
+        _ => {
+            println!("{}", USAGE);
+            process::exit(1);
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn sample_storage() -> CrateStorage {
+        let parsed_input = parse_input("src/five/sample-input.txt").unwrap();
+        parse_configuration(&parsed_input[0]).unwrap()
+    }
+
+    fn sample_moves() -> Vec<Move> {
+        let parsed_input = parse_input("src/five/sample-input.txt").unwrap();
+        parse_moves(&parsed_input[1], 6).unwrap()
+    }
+
     #[test]
     fn test_sample_part1() {
         let expected: &str= "CMZ";
-        let output = solve_part1("src/five/sample-input.txt");
+        let output = solve_part1(&sample_storage(), &sample_moves(), None).unwrap();
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_sample_part2() {
         let expected: &str = "MCD";
-        let output = solve_part2("src/five/sample-input.txt");
+        let output = solve_part2(&sample_storage(), &sample_moves(), None).unwrap();
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_sample_move_lines() {
+        let parsed_input = parse_input("src/five/sample-input.txt").unwrap();
+        let moves = parse_moves(&parsed_input[1], 6).unwrap();
+        assert_eq!(4, moves.len());
+        assert_eq!(Move { amount: 3, from: 1, to: 3, line: 7 }, moves[1]);
+    }
+
+    #[test]
+    fn test_placeholder_answer() {
+        let (start, moves) = parse_file("src/five/test-input-empty.txt").unwrap();
+        assert_eq!(Err(MoveError::EmptyStack { stack: 1 }), solve_part1(&start, &moves, None));
+        assert_eq!(Ok("_B".to_string()), solve_part1(&start, &moves, Some('_')));
+        assert_eq!(Ok("_A".to_string()), solve_part2(&start, &moves, Some('_')));
+    }
+
+    #[test]
+    fn test_bad_files() {
+        assert!(matches!(parse_file("src/five/no-such-input.txt"), Err(InputError::Read(_))));
+        assert!(matches!(parse_file("src/five/test-input-no-moves.txt"), Err(InputError::MissingMoves)));
+    }
+
+    #[test]
+    fn test_malformed_instruction() {
+        let expected = Err(MoveError::Malformed { line: 2, content: "move 1 from to 3".to_string() });
+        let output = parse_moves("move 1 from 2 to 1\nmove 1 from to 3\n", 1);
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_move_from_stack_zero() {
+        let mut storage = sample_storage();
+        let expected = Err(MoveError::InvalidStack { line: 7, stack: 0, amount_stacks: 3 });
+        let output = storage.apply(&Move { amount: 1, from: 0, to: 1, line: 7 }, Crane::CrateMover9000);
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_move_to_missing_stack() {
+        let mut storage = sample_storage();
+        let expected = Err(MoveError::InvalidStack { line: 3, stack: 4, amount_stacks: 3 });
+        let output = storage.apply(&Move { amount: 1, from: 1, to: 4, line: 3 }, Crane::CrateMover9001);
         assert_eq!(expected, output);
     }
+
+    #[test]
+    fn test_move_too_many_crates() {
+        let mut storage = sample_storage();
+        let expected = Err(MoveError::NotEnoughCrates { line: 9, stack: 3, requested: 2, available: 1 });
+        let output = storage.apply(&Move { amount: 2, from: 3, to: 1, line: 9 }, Crane::CrateMover9000);
+        assert_eq!(expected, output);
+        // the rejected move must not have touched the stacks
+        assert_eq!("NDP", storage.top_crates(None).unwrap());
+    }
+
+    #[test]
+    fn test_empty_top() {
+        let mut storage = sample_storage();
+        storage.apply(&Move { amount: 1, from: 3, to: 1, line: 1 }, Crane::CrateMover9000).unwrap();
+        assert_eq!(Err(MoveError::EmptyStack { stack: 3 }), storage.top_crates(None));
+        assert_eq!("PD ", storage.top_crates(Some(' ')).unwrap());
+    }
+
+    #[test]
+    fn test_move_onto_same_stack() {
+        let mut storage = sample_storage();
+        storage.apply(&Move { amount: 2, from: 1, to: 1, line: 1 }, Crane::CrateMover9000).unwrap();
+        storage.apply(&Move { amount: 2, from: 2, to: 2, line: 2 }, Crane::CrateMover9001).unwrap();
+        assert_eq!(vec!['Z', 'N'], storage.crate_stacks[0].crates);
+        assert_eq!(vec!['M', 'C', 'D'], storage.crate_stacks[1].crates);
+    }
+
+    #[test]
+    fn test_reconstruct_sample() {
+        let start = sample_storage();
+        let moves = sample_moves();
+        for crane in [Crane::CrateMover9000, Crane::CrateMover9001] {
+            let final_state = simulate(&start, &moves, crane).unwrap();
+            assert_eq!(start, reconstruct(&final_state, &moves, crane).unwrap());
+            assert!(is_consistent(&start, &moves, &final_state, crane));
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_wrong_crane() {
+        let start = sample_storage();
+        let moves = sample_moves();
+        let final_state = simulate(&start, &moves, Crane::CrateMover9001).unwrap();
+        assert!(!is_consistent(&start, &moves, &final_state, Crane::CrateMover9000));
+    }
+
+    #[test]
+    fn test_reconstruct_impossible() {
+        // only one crate ends up on stack 2, so a move of two onto it cannot
+        // have happened
+        let final_state = parse_configuration("[A] [B]\n 1   2 ").unwrap();
+        let moves = vec![Move { amount: 2, from: 1, to: 2, line: 4 }];
+        let expected = MoveError::CannotUndo { line: 4, from: 1, to: 2, requested: 2, available: 1 };
+        assert_eq!("line 4: cannot undo moving 2 crates from stack 1 to stack 2 holding 1", expected.to_string());
+        assert_eq!(Err(expected), reconstruct(&final_state, &moves, Crane::CrateMover9000));
+    }
+
+    #[test]
+    fn test_drawing_roundtrip() {
+        let start = sample_storage();
+        let parsed_input = parse_input("src/five/sample-input.txt").unwrap();
+        assert_eq!(parsed_input[0], start.drawing());
+        let final_state = simulate(&start, &sample_moves(), Crane::CrateMover9000).unwrap();
+        assert_eq!(final_state, parse_configuration(&final_state.drawing()).unwrap());
+    }
+
+    // Drawings written by hand may leave out the spaces after the last crate
+    // of a row.
+    #[test]
+    fn test_hand_written_drawing() {
+        let storage = parse_configuration("    [D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3").unwrap();
+        assert_eq!(sample_storage(), storage);
+        let storage = parse_configuration("        [D]\n 1   2   3").unwrap();
+        assert_eq!("  D", storage.top_crates(Some(' ')).unwrap());
+    }
+
+    #[test]
+    fn test_bad_drawing() {
+        let output = parse_configuration("[A] [B] [C]\n 1   2 ");
+        assert!(matches!(output, Err(InputError::CrateOutsideStacks { line: 1, amount_stacks: 2 })));
+        let output = parse_configuration("[A] [B]\n[C]");
+        assert!(matches!(output, Err(InputError::StackNumbers { .. })));
+        assert!(matches!(parse_configuration(" 1   3 "), Err(InputError::StackNumbers { .. })));
+        assert!(matches!(parse_configuration(""), Err(InputError::StackNumbers { .. })));
+    }
+
+    // Throughput check for large inputs, run with
+    // `cargo test --release five::test::bench_large_moves -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_large_moves() {
+        use std::time::Instant;
+
+        let amount_stacks = 9;
+        let crates_per_stack = 100_000;
+        let amount_moves = 2_000_000;
+        let move_size = 500;
+        for crane in [Crane::CrateMover9000, Crane::CrateMover9001] {
+            let mut storage = CrateStorage::new(amount_stacks);
+            for i in 0..amount_stacks {
+                for c in 0..crates_per_stack {
+                    storage.push((b'A' + (c % 26) as u8) as char, i);
+                }
+            }
+            // every move shifts a large block to the neighbouring stack; going
+            // round in a circle keeps all stacks well stocked
+            let start = Instant::now();
+            let mut moved = 0;
+            for i in 0..amount_moves {
+                let from = i % amount_stacks + 1;
+                let to = from % amount_stacks + 1;
+                let amount = move_size.min(storage.crate_stacks[from-1].len());
+                storage.apply(&Move { amount, from, to, line: i + 1 }, crane).unwrap();
+                moved += amount;
+            }
+            let elapsed = start.elapsed();
+            println!("{:?}: {} moves, {} crates in {:?} ({:.0} moves/s)",
+                crane, amount_moves, moved, elapsed, amount_moves as f64 / elapsed.as_secs_f64());
+        }
+    }
 }
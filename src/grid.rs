@@ -0,0 +1,273 @@
+// A rectangular grid of cells stored row by row in one vector, for the days
+// whose input is a map. Positions are (row, column) pairs counted from the
+// top left corner.
+
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
+
+// The four directions sharing an edge, which is what neighbors means here.
+pub const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+pub const DIAGONALS: [Direction; 4] = [Direction::UpLeft, Direction::UpRight, Direction::DownLeft, Direction::DownRight];
+
+pub const ALL_DIRECTIONS: [Direction; 8] = [
+    Direction::Up, Direction::Down, Direction::Left, Direction::Right,
+    Direction::UpLeft, Direction::UpRight, Direction::DownLeft, Direction::DownRight,
+];
+
+impl Direction {
+    // (row, column) offset of one step in this direction.
+    pub fn step(&self) -> (isize, isize) {
+        match self {
+            Direction::Up => (-1, 0),
+            Direction::Down => (1, 0),
+            Direction::Left => (0, -1),
+            Direction::Right => (0, 1),
+            Direction::UpLeft => (-1, -1),
+            Direction::UpRight => (-1, 1),
+            Direction::DownLeft => (1, -1),
+            Direction::DownRight => (1, 1),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GridError {
+    Ragged { row: usize, expected: usize, found: usize },
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GridError::Ragged { row, expected, found } => {
+                write!(f, "row {} has {} cells, expected {}", row + 1, found, expected)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    pub fn filled(width: usize, height: usize, value: T) -> Grid<T> where T: Clone {
+        Grid { width, height, cells: vec![value; width * height] }
+    }
+
+    // Builds a grid from its rows, which must all have the same length.
+    pub fn from_rows(rows: Vec<Vec<T>>) -> Result<Grid<T>, GridError> {
+        let width = rows.first().map_or(0, |row| row.len());
+        let height = rows.len();
+        let mut cells = Vec::with_capacity(width * height);
+        for (i, row) in rows.into_iter().enumerate() {
+            if row.len() != width {
+                return Err(GridError::Ragged { row: i, expected: width, found: row.len() });
+            }
+            cells.extend(row);
+        }
+        Ok(Grid { width, height, cells })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    #[allow(dead_code)]
+    pub fn contains(&self, row: isize, column: isize) -> bool {
+        row >= 0 && column >= 0 && (row as usize) < self.height && (column as usize) < self.width
+    }
+
+    #[allow(dead_code)]
+    pub fn get(&self, row: usize, column: usize) -> Option<&T> {
+        if row < self.height && column < self.width {
+            self.cells.get(row * self.width + column)
+        } else {
+            None
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn row(&self, row: usize) -> &[T] {
+        &self.cells[row * self.width..(row + 1) * self.width]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        // `chunks` refuses a size of zero, which an empty grid would give
+        self.cells.chunks(self.width.max(1))
+    }
+
+    #[allow(dead_code)]
+    pub fn column(&self, column: usize) -> impl Iterator<Item = &T> {
+        self.cells.iter().skip(column).step_by(self.width.max(1)).take(self.height)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.cells.iter()
+    }
+
+    // Every position, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize)> {
+        let width = self.width;
+        (0..self.height).flat_map(move |row| (0..width).map(move |column| (row, column)))
+    }
+
+    // The position one step away in each of the four directions, as far as
+    // it is inside the grid.
+    #[allow(dead_code)]
+    pub fn neighbors(&self, row: usize, column: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        DIRECTIONS.iter().filter_map(move |direction| self.offset(row, column, direction.step()))
+    }
+
+    fn offset(&self, row: usize, column: usize, (dr, dc): (isize, isize)) -> Option<(usize, usize)> {
+        let (r, c) = (row as isize + dr, column as isize + dc);
+        if self.contains(r, c) {
+            Some((r as usize, c as usize))
+        } else {
+            None
+        }
+    }
+
+    // The positions from next to (row, column) up to the edge of the grid,
+    // going one `step` at a time.
+    #[allow(dead_code)]
+    pub fn ray_by(&self, row: usize, column: usize, step: (isize, isize)) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut current = (row, column);
+        std::iter::from_fn(move || {
+            current = self.offset(current.0, current.1, step)?;
+            Some(current)
+        })
+    }
+
+    #[allow(dead_code)]
+    pub fn ray(&self, row: usize, column: usize, direction: Direction) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.ray_by(row, column, direction.step())
+    }
+
+    pub fn map<U, F: FnMut(&T) -> U>(&self, f: F) -> Grid<U> {
+        Grid { width: self.width, height: self.height, cells: self.cells.iter().map(f).collect() }
+    }
+}
+
+impl Grid<char> {
+    // One row per line and one cell per character.
+    #[allow(dead_code)]
+    pub fn parse(input: &str) -> Result<Grid<char>, GridError> {
+        Grid::from_rows(input.lines().map(|line| line.chars().collect()).collect())
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        assert!(row < self.height && column < self.width, "({}, {}) outside {}x{} grid", row, column, self.height, self.width);
+        &self.cells[row * self.width + column]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut T {
+        assert!(row < self.height && column < self.width, "({}, {}) outside {}x{} grid", row, column, self.height, self.width);
+        &mut self.cells[row * self.width + column]
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Grid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.rows() {
+            for cell in row {
+                write!(f, "{}", cell)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> Grid<char> {
+        Grid::parse("abc\ndef\n").unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let grid = sample();
+        assert_eq!(3, grid.width());
+        assert_eq!(2, grid.height());
+        assert_eq!('f', grid[(1, 2)]);
+        assert_eq!(Some(&'b'), grid.get(0, 1));
+        assert_eq!(None, grid.get(2, 0));
+        assert_eq!(None, grid.get(0, 3));
+        assert_eq!("abc\ndef\n", grid.to_string());
+    }
+
+    #[test]
+    fn test_ragged() {
+        let expected = Err(GridError::Ragged { row: 1, expected: 3, found: 2 });
+        assert_eq!(expected, Grid::parse("abc\nde\nfgh"));
+    }
+
+    #[test]
+    fn test_empty() {
+        let grid = Grid::parse("").unwrap();
+        assert!(grid.is_empty());
+        assert_eq!(0, grid.rows().count());
+        assert_eq!(0, grid.positions().count());
+        assert_eq!(None, grid.get(0, 0));
+    }
+
+    #[test]
+    fn test_rows_and_columns() {
+        let grid = sample();
+        assert_eq!(vec![&['a', 'b', 'c'][..], &['d', 'e', 'f'][..]], grid.rows().collect::<Vec<_>>());
+        assert_eq!(vec![&'b', &'e'], grid.column(1).collect::<Vec<_>>());
+        assert_eq!(vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)], grid.positions().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_neighbors_and_rays() {
+        let grid = sample();
+        assert_eq!(vec![(1, 0), (0, 1)], grid.neighbors(0, 0).collect::<Vec<_>>());
+        assert_eq!(vec![(0, 1), (1, 0), (1, 2)], grid.neighbors(1, 1).collect::<Vec<_>>());
+        assert_eq!(vec![(1, 1), (1, 0)], grid.ray(1, 2, Direction::Left).collect::<Vec<_>>());
+        assert_eq!(vec![(0, 0)], grid.ray(1, 0, Direction::Up).collect::<Vec<_>>());
+        assert_eq!(0, grid.ray(1, 0, Direction::Down).count());
+        assert_eq!(vec![(1, 1)], grid.ray_by(0, 0, (1, 1)).collect::<Vec<_>>());
+        assert_eq!(vec![(0, 1)], grid.ray(1, 2, Direction::UpLeft).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_map_and_index_mut() {
+        let mut grid = sample().map(|c| *c as u32 - 'a' as u32);
+        grid[(0, 0)] = 9;
+        grid[(1, 1)] += 10;
+        assert_eq!("912\n3145\n", grid.to_string());
+    }
+}
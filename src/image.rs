@@ -0,0 +1,387 @@
+// Writes grids of colours as image files, PPM for simplicity and PNG for
+// everything that wants to open them, black and white ones as PBM, and
+// sequences of them as animated GIFs. The PNG and GIF encoders do not
+// compress, which keeps them short and dependency free.
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::io::{self, Write};
+
+use crate::grid::Grid;
+
+pub type Rgb = [u8; 3];
+
+// Blows every cell up into a `factor` by `factor` square of pixels.
+pub fn upscale<T: Copy + Default>(image: &Grid<T>, factor: usize) -> Grid<T> {
+    let mut scaled = Grid::filled(image.width() * factor, image.height() * factor, T::default());
+    for (row, column) in scaled.positions() {
+        scaled[(row, column)] = image[(row / factor, column / factor)];
+    }
+    scaled
+}
+
+fn check_size<T>(image: &Grid<T>) -> io::Result<()> {
+    if image.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot write an empty image"));
+    }
+    Ok(())
+}
+
+// Binary PPM: a short text header followed by the raw RGB bytes.
+pub fn write_ppm<W: Write>(image: &Grid<Rgb>, out: &mut W) -> io::Result<()> {
+    check_size(image)?;
+    write!(out, "P6\n{} {}\n255\n", image.width(), image.height())?;
+    for pixel in image.iter() {
+        out.write_all(pixel)?;
+    }
+    out.flush()
+}
+
+// Binary PBM: a short text header followed by every row packed into bits,
+// most significant first, with `true` for black.
+pub fn write_pbm<W: Write>(image: &Grid<bool>, out: &mut W) -> io::Result<()> {
+    check_size(image)?;
+    write!(out, "P4\n{} {}\n", image.width(), image.height())?;
+    for row in image.rows() {
+        let packed: Vec<u8> = row.chunks(8)
+            .map(|bits| bits.iter().enumerate().fold(0, |byte, (i, &black)| byte | (black as u8) << (7 - i)))
+            .collect();
+        out.write_all(&packed)?;
+    }
+    out.flush()
+}
+
+fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 == 1 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *entry = c;
+    }
+    table
+}
+
+fn crc32(table: &[u32; 256], data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |c, &b| table[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8))
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), &x| {
+        let a = (a + x as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+// A zlib stream holding `data` in stored blocks of at most 65535 bytes.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        stream.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        stream.push(if blocks.peek().is_none() { 1 } else { 0 });
+        let len = block.len() as u16;
+        stream.extend(len.to_le_bytes());
+        stream.extend((!len).to_le_bytes());
+        stream.extend(block);
+    }
+    stream.extend(adler32(data).to_be_bytes());
+    stream
+}
+
+fn write_chunk<W: Write>(out: &mut W, table: &[u32; 256], kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut checked = kind.to_vec();
+    checked.extend(data);
+    out.write_all(&checked)?;
+    out.write_all(&crc32(table, &checked).to_be_bytes())
+}
+
+// 8 bit RGB PNG. Every scanline gets filter type 0, so the image data is just
+// the pixels with a zero byte in front of each row.
+pub fn write_png<W: Write>(image: &Grid<Rgb>, out: &mut W) -> io::Result<()> {
+    check_size(image)?;
+    let table = crc_table();
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+    let mut header = Vec::new();
+    header.extend((image.width() as u32).to_be_bytes());
+    header.extend((image.height() as u32).to_be_bytes());
+    // bit depth 8, colour type 2 (RGB), default compression, filter and no
+    // interlacing
+    header.extend([8, 2, 0, 0, 0]);
+    write_chunk(out, &table, b"IHDR", &header)?;
+    let mut raw = Vec::with_capacity(image.height() * (3 * image.width() + 1));
+    for row in image.rows() {
+        raw.push(0);
+        for pixel in row {
+            raw.extend(pixel);
+        }
+    }
+    write_chunk(out, &table, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(out, &table, b"IEND", &[])?;
+    out.flush()
+}
+
+// The GIF encoder writes every pixel as an 8 bit LZW code of its own and
+// starts the code table over before it would need wider codes. That leaves
+// room for 128 colours.
+const GIF_MIN_CODE_SIZE: u8 = 7;
+const GIF_COLORS: usize = 1 << GIF_MIN_CODE_SIZE;
+const GIF_CLEAR: u8 = GIF_COLORS as u8;
+const GIF_END: u8 = GIF_CLEAR + 1;
+// Every code after the first following a clear adds an entry to the table,
+// and the codes widen once it reaches 256 entries.
+const GIF_CODES_PER_CLEAR: usize = 256 - (GIF_COLORS + 2);
+
+// The image data of one frame as LZW codes, in sub-blocks of at most 255
+// bytes.
+fn gif_image_data(indexes: &[u8]) -> Vec<u8> {
+    let mut codes = Vec::with_capacity(indexes.len() + indexes.len() / GIF_CODES_PER_CLEAR + 2);
+    for run in indexes.chunks(GIF_CODES_PER_CLEAR) {
+        codes.push(GIF_CLEAR);
+        codes.extend(run);
+    }
+    codes.push(GIF_END);
+    let mut data = vec![GIF_MIN_CODE_SIZE];
+    for block in codes.chunks(255) {
+        data.push(block.len() as u8);
+        data.extend(block);
+    }
+    data.push(0);
+    data
+}
+
+// An animated GIF that loops forever, showing every frame for `delay`
+// hundredths of a second. All frames must have the same size and share at
+// most 128 colours between them.
+pub fn write_gif<W: Write>(frames: &[Grid<Rgb>], delay: u16, out: &mut W) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidInput, message.to_string());
+    let first = frames.first().ok_or_else(|| invalid("cannot write an animation without frames"))?;
+    check_size(first)?;
+    let (width, height) = (first.width(), first.height());
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(invalid("the frames are too large for a GIF"));
+    }
+    if frames.iter().any(|frame| frame.width() != width || frame.height() != height) {
+        return Err(invalid("the frames differ in size"));
+    }
+    let mut palette: Vec<Rgb> = Vec::new();
+    let mut indexes: HashMap<Rgb, u8> = HashMap::new();
+    for &color in frames.iter().flat_map(|frame| frame.iter()) {
+        if let Entry::Vacant(entry) = indexes.entry(color) {
+            if palette.len() == GIF_COLORS {
+                return Err(invalid("the frames have too many colours for a GIF"));
+            }
+            entry.insert(palette.len() as u8);
+            palette.push(color);
+        }
+    }
+    palette.resize(GIF_COLORS, [0, 0, 0]);
+
+    out.write_all(b"GIF89a")?;
+    out.write_all(&(width as u16).to_le_bytes())?;
+    out.write_all(&(height as u16).to_le_bytes())?;
+    // a global colour table of 2^(6 + 1) entries with 8 bits per primary,
+    // background colour 0 and square pixels
+    out.write_all(&[0xf6, 0, 0])?;
+    for color in &palette {
+        out.write_all(color)?;
+    }
+    // the application extension that makes browsers loop the animation
+    out.write_all(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+    for frame in frames {
+        out.write_all(&[0x21, 0xf9, 4, 0])?;
+        out.write_all(&delay.to_le_bytes())?;
+        out.write_all(&[0, 0])?;
+        out.write_all(&[0x2c, 0, 0, 0, 0])?;
+        out.write_all(&(width as u16).to_le_bytes())?;
+        out.write_all(&(height as u16).to_le_bytes())?;
+        out.write_all(&[0])?;
+        let pixels: Vec<u8> = frame.iter().map(|color| indexes[color]).collect();
+        out.write_all(&gif_image_data(&pixels))?;
+    }
+    out.write_all(b"\x3b")?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(0xcbf43926, crc32(&crc_table(), b"123456789"));
+        assert_eq!(0x11e60398, adler32(b"Wikipedia"));
+    }
+
+    #[test]
+    fn test_ppm() {
+        let image = Grid::from_rows(vec![vec![[1, 2, 3], [4, 5, 6]]]).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        write_ppm(&image, &mut output).unwrap();
+        assert_eq!(b"P6\n2 1\n255\n\x01\x02\x03\x04\x05\x06", output.as_slice());
+    }
+
+    #[test]
+    fn test_pbm() {
+        let image = Grid::from_rows(vec![
+            vec![true, false, false, false, false, false, false, false, true, true],
+            vec![false; 10],
+        ]).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        write_pbm(&image, &mut output).unwrap();
+        assert_eq!(b"P4\n10 2\n\x80\xc0\x00\x00", output.as_slice());
+    }
+
+    #[test]
+    fn test_upscale() {
+        let image = Grid::from_rows(vec![vec![[1, 1, 1], [2, 2, 2]]]).unwrap();
+        let expected = Grid::from_rows(vec![
+            vec![[1, 1, 1], [1, 1, 1], [2, 2, 2], [2, 2, 2]],
+            vec![[1, 1, 1], [1, 1, 1], [2, 2, 2], [2, 2, 2]],
+        ]).unwrap();
+        assert_eq!(expected, upscale(&image, 2));
+    }
+
+    #[test]
+    fn test_empty_image() {
+        let image: Grid<Rgb> = Grid::from_rows(Vec::new()).unwrap();
+        assert!(write_png(&image, &mut Vec::new()).is_err());
+        assert!(write_ppm(&image, &mut Vec::new()).is_err());
+        assert!(write_pbm(&Grid::from_rows(Vec::new()).unwrap(), &mut Vec::new()).is_err());
+        assert!(write_gif(&[image], 10, &mut Vec::new()).is_err());
+        assert!(write_gif(&[], 10, &mut Vec::new()).is_err());
+    }
+
+    // Walks the chunks of a PNG with more image data than fits into one
+    // stored block, checking every CRC and unpacking the stored blocks again.
+    #[test]
+    fn test_png_structure() {
+        let image = Grid::filled(200, 150, [0, 0, 0]).map(|_| [7, 8, 9]);
+        let mut png: Vec<u8> = Vec::new();
+        write_png(&image, &mut png).unwrap();
+        assert_eq!(b"\x89PNG\r\n\x1a\n", &png[..8]);
+        let table = crc_table();
+        let mut chunks: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let checked = &rest[4..8 + len];
+            let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+            assert_eq!(crc32(&table, checked), crc);
+            chunks.push((checked[..4].to_vec(), checked[4..].to_vec()));
+            rest = &rest[12 + len..];
+        }
+        let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| kind.as_slice()).collect();
+        assert_eq!(vec![&b"IHDR"[..], &b"IDAT"[..], &b"IEND"[..]], kinds);
+        assert_eq!([0, 0, 0, 200, 0, 0, 0, 150, 8, 2, 0, 0, 0], chunks[0].1.as_slice());
+
+        let stream = &chunks[1].1;
+        let mut raw: Vec<u8> = Vec::new();
+        let mut position = 2;
+        loop {
+            let last = stream[position] == 1;
+            let len = u16::from_le_bytes([stream[position + 1], stream[position + 2]]) as usize;
+            let nlen = u16::from_le_bytes([stream[position + 3], stream[position + 4]]) as usize;
+            assert_eq!(0xffff, len ^ nlen);
+            raw.extend(&stream[position + 5..position + 5 + len]);
+            position += 5 + len;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(150 * (1 + 3 * 200), raw.len());
+        assert_eq!(&[0, 7, 8, 9, 7][..], &raw[..5]);
+        assert_eq!(adler32(&raw).to_be_bytes(), stream[position..]);
+    }
+
+    // A plain LZW decoder, with codes that widen as the table grows like any
+    // GIF reader's, for the image data of one frame.
+    fn lzw_decode(data: &[u8]) -> Vec<u8> {
+        let min_code_size = data[0] as u32;
+        let mut codes: Vec<u8> = Vec::new();
+        let mut rest = &data[1..];
+        while rest[0] != 0 {
+            let len = rest[0] as usize;
+            codes.extend(&rest[1..1 + len]);
+            rest = &rest[1 + len..];
+        }
+        assert_eq!(&[0][..], rest);
+        let (clear, end) = (1u32 << min_code_size, (1u32 << min_code_size) + 1);
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut width = min_code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let mut output = Vec::new();
+        let mut bit = 0;
+        loop {
+            let code = (0..width).fold(0, |code, i| {
+                let position = bit + i as usize;
+                code | (((codes[position / 8] >> (position % 8)) & 1) as u32) << i
+            });
+            bit += width as usize;
+            if code == clear {
+                table = (0..clear).map(|c| vec![c as u8]).chain([vec![], vec![]]).collect();
+                width = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                break;
+            }
+            let entry = match (table.get(code as usize), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => [previous.clone(), vec![previous[0]]].concat(),
+                (None, None) => panic!("unknown first code {}", code),
+            };
+            if let Some(previous) = previous {
+                table.push([previous, vec![entry[0]]].concat());
+                if table.len() == 1 << width && width < 12 {
+                    width += 1;
+                }
+            }
+            output.extend(&entry);
+            previous = Some(entry);
+        }
+        output
+    }
+
+    #[test]
+    fn test_gif_image_data() {
+        let pixels: Vec<u8> = (0..1000).map(|i| (i * 7 % 128) as u8).collect();
+        let data = gif_image_data(&pixels);
+        assert_eq!(pixels, lzw_decode(&data));
+        assert_eq!(Vec::<u8>::new(), lzw_decode(&gif_image_data(&[])));
+    }
+
+    #[test]
+    fn test_gif_structure() {
+        let red = Grid::from_rows(vec![vec![[255, 0, 0], [0, 0, 0]]]).unwrap();
+        let blue = Grid::from_rows(vec![vec![[0, 0, 0], [0, 0, 255]]]).unwrap();
+        let mut gif: Vec<u8> = Vec::new();
+        write_gif(&[red.clone(), blue], 5, &mut gif).unwrap();
+        assert_eq!(b"GIF89a\x02\x00\x01\x00\xf6\x00\x00", &gif[..13]);
+        let palette = &gif[13..13 + 3 * GIF_COLORS];
+        assert_eq!(&[255, 0, 0, 0, 0, 0, 0, 0, 255, 0, 0, 0][..], &palette[..12]);
+        let mut rest = &gif[13 + 3 * GIF_COLORS + 19..];
+        let mut frames: Vec<Vec<u8>> = Vec::new();
+        while rest[0] == 0x21 {
+            assert_eq!(&[0x21, 0xf9, 4, 0, 5, 0, 0, 0, 0x2c, 0, 0, 0, 0, 2, 0, 1, 0, 0][..], &rest[..18]);
+            let mut end = 19;
+            while rest[end] != 0 {
+                end += 1 + rest[end] as usize;
+            }
+            frames.push(lzw_decode(&rest[18..end + 1]));
+            rest = &rest[end + 1..];
+        }
+        assert_eq!(vec![vec![0, 1], vec![1, 2]], frames);
+        assert_eq!(b"\x3b", rest);
+
+        let tall = Grid::filled(1, 2, [0, 0, 0]);
+        assert!(write_gif(&[red, tall], 5, &mut Vec::new()).is_err());
+        let colorful = Grid::from_rows(vec![(0..=128).map(|i| [i as u8, 0, 0]).collect()]).unwrap();
+        assert!(write_gif(&[colorful], 5, &mut Vec::new()).is_err());
+    }
+}
@@ -1,4 +1,7 @@
 use std::{env, process};
+mod cli;
+mod grid;
+mod image;
 mod one;
 mod two;
 mod three;
@@ -10,12 +13,10 @@ mod eight;
 mod ten;
 
 fn main() {
-    let day: u32;
-
     let args = env::args().collect::<Vec<String>>();
 
-    if args.len() == 2 {
-        day = match args[1].parse::<u32>() {
+    let day: u32 = if args.len() >= 2 {
+        match args[1].parse::<u32>() {
             Ok(n) => n,
             Err(_) => {
                 println!("Error parsing day number: \"{}\"", args[1]);
@@ -23,23 +24,34 @@ fn main() {
             }
         }
     } else {
-        println!("usage: advent_of_code_2022 <day>");
+        println!("usage: advent_of_code_2022 <day> [options]");
         process::exit(1);
-    }
+    };
+    let options = &args[2..];
 
-    // Run soluntion(s) for that day
-    println!("Advent of Code 2022 --- Day {}", day);
+    // Run soluntion(s) for that day. Options can ask for data meant to be
+    // redirected, like a session transcript or a program, so the banner must
+    // not end up in it then.
+    if options.is_empty() {
+        println!("Advent of Code 2022 --- Day {}", day);
+    } else {
+        eprintln!("Advent of Code 2022 --- Day {}", day);
+    }
 
     match day {
+        5 => five::run(options),
+        6 => six::run(options),
+        7 => seven::run(options),
+        8 => eight::run(options),
+        10 => ten::run(options),
+        _ if !options.is_empty() => {
+            println!("Day {} takes no options", day);
+            process::exit(1);
+        }
         1 => one::solve(),
         2 => two::solve(),
         3 => three::solve(),
         4 => four::solve(),
-        5 => five::solve(),
-        6 => six::solve(),
-        7 => seven::solve(),
-        8 => eight::solve(),
-        10 => ten::solve(),
         _ => {
             println!("Day {} not (yet) solved.", day);
         }
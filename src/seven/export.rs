@@ -0,0 +1,271 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+use super::{EntryType, Filesystem, SortOrder, ROOT};
+
+const BLOCK: usize = 512;
+
+// Names come straight from the terminal session, so make sure none of them
+// can escape the directory or archive they are written into.
+fn check_name(name: &str) -> io::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains('/') || name.contains('\0') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("refusing to export entry \"{}\"", name)));
+    }
+    Ok(())
+}
+
+// Writes `value` as a NUL terminated octal number filling `field`, or in the
+// GNU base-256 encoding if it does not fit, which tar uses for huge files.
+fn write_number(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let octal = format!("{:0width$o}", value, width = digits);
+    if octal.len() <= digits {
+        field[..digits].copy_from_slice(octal.as_bytes());
+        field[digits] = 0;
+    } else {
+        let bytes = value.to_be_bytes();
+        field.fill(0);
+        let start = field.len() - bytes.len();
+        field[start..].copy_from_slice(&bytes);
+        field[0] |= 0x80;
+    }
+}
+
+// A ustar header block. Paths longer than the 100 byte name field are split
+// into the prefix field at a `/`.
+fn tar_header(path: &str, t: &EntryType, size: u64) -> io::Result<[u8; BLOCK]> {
+    let mut header = [0u8; BLOCK];
+    let (prefix, name) = if path.len() <= 100 {
+        ("", path)
+    } else {
+        // the prefix field holds 155 bytes, and a `/` is never part of a
+        // multibyte character, so splitting at one is safe
+        let trimmed = path.trim_end_matches('/').as_bytes();
+        match trimmed[..trimmed.len().min(156)].iter().rposition(|&b| b == b'/') {
+            Some(split) if path.len() - split - 1 <= 100 => (&path[..split], &path[split + 1..]),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("path too long for tar: {}", path))),
+        }
+    };
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    let (mode, typeflag) = match t {
+        EntryType::Dir => (0o755, b'5'),
+        EntryType::File => (0o644, b'0'),
+    };
+    write_number(&mut header[100..108], mode);
+    write_number(&mut header[108..116], 0);
+    write_number(&mut header[116..124], 0);
+    write_number(&mut header[124..136], size);
+    write_number(&mut header[136..148], 0);
+    header[156] = typeflag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    // the checksum is computed with its own field filled with spaces
+    header[148..156].fill(b' ');
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+    Ok(header)
+}
+
+impl Filesystem {
+    // Recreates the tree below `target`, which must not exist yet. Files are
+    // created sparse with their listed size, so even large inputs take
+    // hardly any disk space.
+    pub fn export_dir(&self, target: &Path) -> io::Result<()> {
+        fs::create_dir(target)?;
+        self.export_children(ROOT, target)
+    }
+
+    fn export_children(&self, dir: usize, target: &Path) -> io::Result<()> {
+        for child in self.sorted_children(dir, SortOrder::Name) {
+            let node = &self.nodes[child];
+            check_name(&node.name)?;
+            let path = target.join(&node.name);
+            match node.t {
+                EntryType::Dir => {
+                    fs::create_dir(&path)?;
+                    self.export_children(child, &path)?;
+                },
+                EntryType::File => File::create(&path)?.set_len(node.size)?,
+            }
+        }
+        Ok(())
+    }
+
+    // Writes the tree as a tar archive with every file filled with zeros.
+    pub fn write_tar<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.write_tar_children(ROOT, "", out)?;
+        out.write_all(&[0u8; 2 * BLOCK])?;
+        out.flush()
+    }
+
+    fn write_tar_children<W: Write>(&self, dir: usize, prefix: &str, out: &mut W) -> io::Result<()> {
+        let zeros = [0u8; 1 << 16];
+        for child in self.sorted_children(dir, SortOrder::Name) {
+            let node = &self.nodes[child];
+            check_name(&node.name)?;
+            match node.t {
+                EntryType::Dir => {
+                    let path = format!("{}{}/", prefix, node.name);
+                    out.write_all(&tar_header(&path, &node.t, 0)?)?;
+                    self.write_tar_children(child, &path, out)?;
+                },
+                EntryType::File => {
+                    let path = format!("{}{}", prefix, node.name);
+                    out.write_all(&tar_header(&path, &node.t, node.size)?)?;
+                    let padding = (BLOCK as u64 - node.size % BLOCK as u64) % BLOCK as u64;
+                    let mut remaining = node.size + padding;
+                    while remaining > 0 {
+                        let chunk = remaining.min(zeros.len() as u64) as usize;
+                        out.write_all(&zeros[..chunk])?;
+                        remaining -= chunk as u64;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // Builds the tree from a real directory. Symlinks and special files are
+    // left out, as are entries whose names cannot be written in a `ls`
+    // listing; their paths are returned so the caller can report them.
+    pub fn from_dir(source: &Path) -> io::Result<(Filesystem, Vec<String>)> {
+        let mut filesystem = Filesystem::new();
+        let mut skipped: Vec<String> = Vec::new();
+        filesystem.read_dir(ROOT, source, &mut skipped)?;
+        filesystem.compute_sizes();
+        Ok((filesystem, skipped))
+    }
+
+    fn read_dir(&mut self, dir: usize, source: &Path, skipped: &mut Vec<String>) -> io::Result<()> {
+        let mut entries: Vec<fs::DirEntry> = fs::read_dir(source)?.collect::<io::Result<_>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let metadata = entry.metadata()?;
+            if name.chars().any(|c| c.is_whitespace()) || !(metadata.is_dir() || metadata.is_file()) {
+                skipped.push(entry.path().display().to_string());
+            } else if metadata.is_dir() {
+                let child = self.add(dir, &name, EntryType::Dir, 0);
+                self.read_dir(child, &entry.path(), skipped)?;
+            } else {
+                self.add(dir, &name, EntryType::File, metadata.len());
+            }
+        }
+        Ok(())
+    }
+
+    // The terminal session that explores the whole tree depth first, in the
+    // same format as the puzzle input.
+    pub fn transcript(&self) -> String {
+        let mut output = String::from("$ cd /\n");
+        self.transcript_dir(ROOT, &mut output);
+        output
+    }
+
+    fn transcript_dir(&self, dir: usize, output: &mut String) {
+        let children = self.sorted_children(dir, SortOrder::Name);
+        output.push_str("$ ls\n");
+        for &child in &children {
+            let node = &self.nodes[child];
+            match node.t {
+                EntryType::Dir => output.push_str(&format!("dir {}\n", node.name)),
+                EntryType::File => output.push_str(&format!("{} {}\n", node.size, node.name)),
+            }
+        }
+        for &child in children.iter().filter(|&&c| self.nodes[c].t == EntryType::Dir) {
+            output.push_str(&format!("$ cd {}\n", self.nodes[child].name));
+            self.transcript_dir(child, output);
+            output.push_str("$ cd ..\n");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{parse_input, parse_session, TreeOptions};
+    use std::env;
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = env::temp_dir().join(format!("aoc2022-seven-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_transcript_roundtrip() {
+        let filesystem = parse_input("src/seven/sample-input.txt").unwrap();
+        let (reparsed, warnings) = parse_session(&filesystem.transcript());
+        assert!(warnings.is_empty());
+        let options = TreeOptions::default();
+        assert_eq!(filesystem.render_tree(&options), reparsed.render_tree(&options));
+        assert_eq!(filesystem.nodes[ROOT].size, reparsed.nodes[ROOT].size);
+    }
+
+    #[test]
+    fn test_export_dir_roundtrip() {
+        let filesystem = parse_input("src/seven/sample-input.txt").unwrap();
+        let target = scratch_dir("export");
+        filesystem.export_dir(&target).unwrap();
+        assert_eq!(584, fs::metadata(target.join("a/e/i")).unwrap().len());
+        // exporting twice must not overwrite anything
+        assert!(filesystem.export_dir(&target).is_err());
+        let (imported, skipped) = Filesystem::from_dir(&target).unwrap();
+        fs::remove_dir_all(&target).unwrap();
+        assert!(skipped.is_empty());
+        assert_eq!(filesystem.transcript(), imported.transcript());
+    }
+
+    #[test]
+    fn test_export_refuses_escaping_names() {
+        let (filesystem, _) = parse_session("$ cd /\n$ ls\n1 ..\n");
+        let target = scratch_dir("escape");
+        let result = filesystem.export_dir(&target);
+        let _ = fs::remove_dir_all(&target);
+        assert!(result.is_err());
+        assert!(filesystem.write_tar(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_write_tar() {
+        let (filesystem, _) = parse_session("$ cd /\n$ ls\ndir a\n700 b\n$ cd a\n$ ls\n3 c\n");
+        let mut archive: Vec<u8> = Vec::new();
+        filesystem.write_tar(&mut archive).unwrap();
+        // a/ header, a/c header and one data block, b header and two data
+        // blocks, then the two closing blocks
+        assert_eq!(8 * BLOCK, archive.len());
+        assert_eq!(b"a/\0", &archive[..3]);
+        assert_eq!(b'5', archive[156]);
+        assert_eq!(b"ustar\0", &archive[257..263]);
+        assert_eq!(b"a/c\0", &archive[BLOCK..BLOCK + 4]);
+        assert_eq!(b"00000000003\0", &archive[BLOCK + 124..BLOCK + 136]);
+        assert_eq!(b"00000001274\0", &archive[3 * BLOCK + 124..3 * BLOCK + 136]);
+        let checksum: u32 = archive[..BLOCK].iter().enumerate()
+            .map(|(i, &b)| if (148..156).contains(&i) { b' ' as u32 } else { b as u32 })
+            .sum();
+        let stored = std::str::from_utf8(&archive[148..154]).unwrap();
+        assert_eq!(checksum, u32::from_str_radix(stored, 8).unwrap());
+    }
+
+    #[test]
+    fn test_long_paths() {
+        // byte 156 falls in the middle of an é
+        let path = format!("{}/{}", "a".repeat(100), "é".repeat(40));
+        let header = tar_header(&path, &EntryType::File, 0).unwrap();
+        assert_eq!("é".repeat(40).as_bytes(), &header[..80]);
+        assert_eq!(0, header[80]);
+        assert_eq!("a".repeat(100).as_bytes(), &header[345..445]);
+        assert!(tar_header(&"é".repeat(90), &EntryType::File, 0).is_err());
+        let path = format!("{}/{}", "é".repeat(80), "b");
+        assert!(tar_header(&path, &EntryType::File, 0).is_err());
+    }
+
+    #[test]
+    fn test_huge_size_in_base_256() {
+        let mut field = [0u8; 12];
+        write_number(&mut field, 1 << 40);
+        assert_eq!([0x80, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0], field);
+    }
+}
@@ -1,10 +1,34 @@
-use std::cmp::Ordering;
-use std::fs;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::process;
 
+use crate::cli::{next_number, next_path};
+
+mod export;
+mod shell;
+
+// A command from the terminal session with its output, each tagged with its
+// line number in the session.
 #[derive(Debug)]
 struct ExecutedCommand {
+    pub line: usize,
     pub command: String,
-    pub results: Vec<String>,
+    pub results: Vec<(usize, String)>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct ParseWarning {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -13,185 +37,535 @@ enum EntryType {
     Dir,
 }
 
+// One file or directory in the tree. Parent and children are indexes into
+// the `Filesystem` arena. For directories `size` is the total size of
+// everything below them once `compute_sizes` has run.
 #[derive(Debug, Eq, PartialEq)]
-struct PathEntry {
-    pub path: String,
-    pub size: u32,
+struct Node {
+    pub name: String,
+    pub size: u64,
     pub t: EntryType,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Filesystem {
+    pub nodes: Vec<Node>,
+}
+
+const ROOT: usize = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FreeSpace {
+    // there is already enough free space
+    Enough,
+    // deleting this directory frees enough
+    Delete(usize),
+    // not even deleting everything frees enough
+    Impossible,
 }
 
-impl Ord for PathEntry {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.size.cmp(&other.size)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortOrder {
+    Name,
+    Size,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TreeOptions {
+    pub sort: SortOrder,
+    pub max_depth: Option<usize>,
+    pub human: bool,
+}
+
+impl Default for TreeOptions {
+    fn default() -> TreeOptions {
+        TreeOptions { sort: SortOrder::Name, max_depth: None, human: false }
     }
 }
 
-impl PartialOrd for PathEntry {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+// Size with a binary unit suffix, the way `du -h` prints it.
+fn human_size(size: u64) -> String {
+    let units = ["K", "M", "G", "T"];
+    if size < 1024 {
+        return size.to_string();
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 {
+        format!("{:.1}{}", value, units[unit])
+    } else {
+        format!("{:.0}{}", value, units[unit])
     }
 }
 
-#[derive(Debug, Eq, PartialEq, PartialOrd)]
-struct Filesystem {
-    pub content: Vec<PathEntry>,
+impl Filesystem {
+    fn new() -> Filesystem {
+        let root = Node {
+            name: "/".to_string(),
+            size: 0,
+            t: EntryType::Dir,
+            parent: None,
+            children: Vec::new(),
+        };
+        Filesystem { nodes: vec![root] }
+    }
+
+    fn add(&mut self, parent: usize, name: &str, t: EntryType, size: u64) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            size,
+            t,
+            parent: Some(parent),
+            children: Vec::new(),
+        });
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    fn child(&self, dir: usize, name: &str) -> Option<usize> {
+        self.nodes[dir].children.iter().copied().find(|&c| self.nodes[c].name == name)
+    }
+
+    // Adds an entry seen in a listing unless `dir` already holds it, so
+    // listing a directory twice does not count its files twice. Returns a
+    // message if the new listing disagrees with the earlier one.
+    fn add_entry(&mut self, dir: usize, name: &str, t: EntryType, size: u64) -> Option<String> {
+        let id = match self.child(dir, name) {
+            None => {
+                self.add(dir, name, t, size);
+                return None;
+            },
+            Some(id) => id,
+        };
+        if self.nodes[id].t != t {
+            return Some(format!("\"{}\" listed as both file and directory, keeping the first", name));
+        }
+        if t == EntryType::File && self.nodes[id].size != size {
+            let previous = self.nodes[id].size;
+            self.nodes[id].size = size;
+            return Some(format!("size of \"{}\" changed from {} to {}", name, previous, size));
+        }
+        None
+    }
+
+    // Follows a `cd` target from `dir`. Absolute targets start at the root,
+    // and every component may be `..`, `.` or a directory name. Directories
+    // that were never listed are created on the way.
+    fn change_dir(&mut self, dir: usize, target: &str) -> Result<usize, String> {
+        let mut current = if target.starts_with('/') { ROOT } else { dir };
+        for component in target.split('/').filter(|c| !c.is_empty()) {
+            current = match component {
+                "." => current,
+                ".." => match self.nodes[current].parent {
+                    Some(parent) => parent,
+                    None => return Err(format!("\"cd {}\" goes above the root", target)),
+                },
+                name => match self.child(current, name) {
+                    Some(id) if self.nodes[id].t == EntryType::Dir => id,
+                    Some(_) => return Err(format!("\"cd {}\": \"{}\" is a file", target, name)),
+                    None => self.add(current, name, EntryType::Dir, 0),
+                },
+            };
+        }
+        Ok(current)
+    }
+
+    // Adds up directory sizes in a single post-order pass, saturating rather
+    // than wrapping on absurdly large inputs. Nodes are only ever appended
+    // below an existing parent, so every child has a higher index than its
+    // parent and walking the arena backwards visits all children before their
+    // parent.
+    fn compute_sizes(&mut self) {
+        for node in self.nodes.iter_mut() {
+            if node.t == EntryType::Dir {
+                node.size = 0;
+            }
+        }
+        for id in (1..self.nodes.len()).rev() {
+            let size = self.nodes[id].size;
+            if let Some(parent) = self.nodes[id].parent {
+                self.nodes[parent].size = self.nodes[parent].size.saturating_add(size);
+            }
+        }
+    }
+
+    // Children of `dir` in listing order: by name, or largest first with
+    // ties broken by name.
+    fn sorted_children(&self, dir: usize, sort: SortOrder) -> Vec<usize> {
+        let mut children = self.nodes[dir].children.clone();
+        match sort {
+            SortOrder::Name => children.sort_by(|&a, &b| self.nodes[a].name.cmp(&self.nodes[b].name)),
+            SortOrder::Size => children.sort_by(|&a, &b| {
+                self.nodes[b].size.cmp(&self.nodes[a].size)
+                    .then_with(|| self.nodes[a].name.cmp(&self.nodes[b].name))
+            }),
+        }
+        children
+    }
+
+    // Renders the tree in the style of the puzzle statement, one entry per
+    // line indented by two spaces per level.
+    fn render_tree(&self, options: &TreeOptions) -> String {
+        let mut output = String::new();
+        self.render_node(ROOT, 0, options, &mut output);
+        output
+    }
+
+    fn render_node(&self, id: usize, depth: usize, options: &TreeOptions, output: &mut String) {
+        let node = &self.nodes[id];
+        output.push_str(&"  ".repeat(depth));
+        match node.t {
+            EntryType::Dir => output.push_str(&format!("- {} (dir)\n", node.name)),
+            EntryType::File => {
+                let size = if options.human { human_size(node.size) } else { node.size.to_string() };
+                output.push_str(&format!("- {} (file, size={})\n", node.name, size));
+            },
+        }
+        if options.max_depth.is_none_or(|max| depth < max) {
+            for child in self.sorted_children(id, options.sort) {
+                self.render_node(child, depth + 1, options, output);
+            }
+        }
+    }
+
+    fn directories(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.nodes.len()).filter(|&id| self.nodes[id].t == EntryType::Dir)
+    }
+
+    // The `n` biggest directories, largest first.
+    fn largest_directories(&self, n: usize) -> Vec<usize> {
+        let mut directories: Vec<usize> = self.directories().collect();
+        directories.sort_by(|&a, &b| {
+            self.nodes[b].size.cmp(&self.nodes[a].size)
+                .then_with(|| self.path(a).cmp(&self.path(b)))
+        });
+        directories.truncate(n);
+        directories
+    }
+
+    // Directories with a total size from `min` to `max` inclusive, by path.
+    fn directories_between(&self, min: u64, max: u64) -> Vec<usize> {
+        let mut directories: Vec<usize> = self.directories()
+            .filter(|&id| self.nodes[id].size >= min && self.nodes[id].size <= max)
+            .collect();
+        directories.sort_by_key(|&id| self.path(id));
+        directories
+    }
+
+    // Total file size per extension, largest first. Files without an
+    // extension (including dot files such as `.profile`) are grouped under
+    // an empty extension.
+    fn size_by_extension(&self) -> Vec<(String, u64)> {
+        let mut totals: HashMap<&str, u64> = HashMap::new();
+        for node in self.nodes.iter().filter(|n| n.t == EntryType::File) {
+            let extension = match node.name.rsplit_once('.') {
+                Some((stem, extension)) if !stem.is_empty() => extension,
+                _ => "",
+            };
+            let total = totals.entry(extension).or_insert(0);
+            *total = total.saturating_add(node.size);
+        }
+        let mut totals: Vec<(String, u64)> = totals.into_iter().map(|(e, size)| (e.to_string(), size)).collect();
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        totals
+    }
+
+    // The smallest directory whose deletion leaves at least `required` bytes
+    // free on a disk of `disk_size` bytes. A filesystem bigger than the disk
+    // counts as having no free space at all.
+    fn smallest_to_free(&self, disk_size: u64, required: u64) -> FreeSpace {
+        let size_used = self.nodes[ROOT].size;
+        let needed_space = required.saturating_sub(disk_size.saturating_sub(size_used));
+        if needed_space == 0 {
+            return FreeSpace::Enough;
+        }
+        match self.directories()
+            .filter(|&id| self.nodes[id].size >= needed_space)
+            .min_by_key(|&id| self.nodes[id].size) {
+            Some(dir) => FreeSpace::Delete(dir),
+            None => FreeSpace::Impossible,
+        }
+    }
+
+    fn path(&self, id: usize) -> String {
+        let mut names: Vec<&str> = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.nodes[current].parent {
+            names.push(&self.nodes[current].name);
+            current = parent;
+        }
+        names.reverse();
+        let mut path = "/".to_string();
+        path.push_str(&names.join("/"));
+        if self.nodes[id].t == EntryType::Dir && id != ROOT {
+            path.push('/');
+        }
+        path
+    }
 }
 
 
-fn parse_input(input_file: &str) -> Filesystem {
-    let content = fs::read_to_string(input_file).unwrap();
+fn parse_input(input_file: &str) -> io::Result<Filesystem> {
+    let content = fs::read_to_string(input_file)?;
+    let (filesystem, warnings) = parse_session(&content);
+    for warning in warnings {
+        println!("Warning: {}", warning);
+    }
+    Ok(filesystem)
+}
+
+// For the command line, where a session that cannot be read ends the
+// program.
+fn parse_or_exit(input_file: &str) -> Filesystem {
+    match parse_input(input_file) {
+        Ok(filesystem) => filesystem,
+        Err(e) => {
+            println!("Error reading {}: {}", input_file, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn parse_session(content: &str) -> (Filesystem, Vec<ParseWarning>) {
+    let mut warnings: Vec<ParseWarning> = Vec::new();
     let mut executed_commands: Vec<ExecutedCommand> = Vec::new();
-    let mut last_command = 0;
-    for line in content.lines() {
-        if &line[..1] == "$" {
+    for (i, line) in content.lines().enumerate() {
+        if line.starts_with('$') {
             let ec = ExecutedCommand {
+                line: i + 1,
                 command : line.to_string(),
-                results : Vec::<String>::new(),
+                results : Vec::new(),
             };
             executed_commands.push(ec);
-            last_command += 1;
+        } else if let Some(last_command) = executed_commands.last_mut() {
+            last_command.results.push((i + 1, line.to_string()));
         } else {
-            executed_commands[last_command-1].results.push(line.to_string());
+            warnings.push(ParseWarning { line: i + 1, message: format!("output \"{}\" before any command", line) });
         }
     }
-    let mut current_dir = "/".to_string();
-    let mut filesystem = Filesystem {
-        content : Vec::new(),
-    };
+    let mut current_dir = ROOT;
+    let mut filesystem = Filesystem::new();
     for command in executed_commands {
-        if command.command.starts_with("$ cd /") {
-            let root = PathEntry {
-                path: "/".to_string(),
-                size: 0,
-                t: EntryType::Dir,
-            };
-            filesystem.content.push(root);
-        } else if command.command.starts_with("$ cd ..") {
-            // directory up
-            let split: Vec<&str> = current_dir.split("/").collect::<Vec<_>>();
-            let len = split.len();
-            let mut joined = split[..len-2].join("/");
-            if ! joined.starts_with("/") {
-                current_dir = "/".to_string();
-                continue;
-            }
-            joined.push('/');
-            current_dir = joined;
-        } else if command.command.starts_with("$ cd ") {
-            // visit directory thats following the "$ cd "
-            current_dir.push_str((&command.command[5..]).clone());
-            current_dir.push('/');
-        } else if command.command == "$ ls" {
-            // check the results of the command and add according dir entries
-            for result in command.results {
-                if result.starts_with("dir ") {
-                    let mut name = (&result[4..]).clone().to_string();
-                    name.push('/');
-                    let mut path: String = current_dir.clone();
-                    path.push_str(&name);
-                    let dir = PathEntry {
-                        size : 0,
-                        path: path,
-                        t: EntryType::Dir,
-                    };
-                    filesystem.content.push(dir);
-                } else {
-                    // create file in directory
-                    let split: Vec<_> = result.split(" ").collect();
-                    let mut path: String = current_dir.clone();
-                    let name = split[1].to_string();
-                    path.push_str(&name);
-                    let size = split[0].parse::<u32>().unwrap();
-                    let file = PathEntry {
-                        path : path,
-                        size : size,
-                        t: EntryType::File,
+        let mut warn = |line: usize, message: String| warnings.push(ParseWarning { line, message });
+        let words: Vec<&str> = command.command.split_whitespace().collect();
+        match words.as_slice() {
+            ["$", "cd", target] => match filesystem.change_dir(current_dir, target) {
+                Ok(dir) => current_dir = dir,
+                // like a shell, stay where we are
+                Err(message) => warn(command.line, message),
+            },
+            ["$", "ls"] => {
+                // check the results of the command and add according dir entries
+                for (line, result) in command.results {
+                    let message = match result.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                        ["dir", name] => filesystem.add_entry(current_dir, name, EntryType::Dir, 0),
+                        [size, name] => match size.parse::<u64>() {
+                            Ok(size) => filesystem.add_entry(current_dir, name, EntryType::File, size),
+                            Err(_) => Some(format!("unrecognized ls output \"{}\"", result)),
+                        },
+                        _ => Some(format!("unrecognized ls output \"{}\"", result)),
                     };
-                    filesystem.content.push(file);
+                    if let Some(message) = message {
+                        warn(line, message);
+                    }
                 }
-            }
+                continue;
+            },
+            _ => warn(command.line, format!("unrecognized command \"{}\"", command.command)),
+        }
+        for (line, result) in command.results {
+            warn(line, format!("unexpected output \"{}\"", result));
         }
     }
-//    println!("{:?}", filesystem);
-    filesystem
+    filesystem.compute_sizes();
+    (filesystem, warnings)
 }
 
-fn solve_part1(input: &str) -> u32 {
-    let filesystem = parse_input(input);
-    let mut directories: Vec<PathEntry> = Vec::new();
-    let mut files: Vec<PathEntry> = Vec::new();
-    for path_entry in filesystem.content {
-        if path_entry.t == EntryType::File {
-            files.push(path_entry);
-        } else {
-            directories.push(path_entry);
-        }
+const DISK_SIZE: u64 = 70000000;
+const REQUIRED_SPACE: u64 = 30000000;
+
+fn solve_part1(filesystem: &Filesystem) -> u64 {
+    filesystem.directories_between(0, 100000 - 1)
+        .iter()
+        .map(|&id| filesystem.nodes[id].size)
+        .sum()
+}
+
+// Size of the directory to delete, or `None` if the update already fits
+// without deleting anything. With the puzzle's disk even deleting everything
+// is always enough, but that depends on the constants.
+fn solve_part2(filesystem: &Filesystem) -> Result<Option<u64>, &'static str> {
+    match filesystem.smallest_to_free(DISK_SIZE, REQUIRED_SPACE) {
+        FreeSpace::Enough => Ok(None),
+        FreeSpace::Delete(id) => Ok(Some(filesystem.nodes[id].size)),
+        FreeSpace::Impossible => Err("no single directory frees enough space"),
     }
-    let mut res = 0;
-    for mut d in directories {
-        let mut sum = 0;
-        for f in &files {
-            if f.path.starts_with(&d.path) {
-                sum += f.size;
-            }
-        }
-        d.size = sum;
-        if sum < 100000 {
-            res += sum;
-        }
+}
+
+pub fn solve() {
+    let filesystem = parse_or_exit("src/seven/input.txt");
+    println!("Solution for part 1 {}", solve_part1(&filesystem));
+    match solve_part2(&filesystem) {
+        Ok(Some(res)) => println!("Solution for part 2 {}", res),
+        Ok(None) => println!("Solution for part 2: nothing needs to be deleted"),
+        Err(e) => println!("Error in part 2: {}", e),
     }
-    res
 }
 
-fn solve_part2(input: &str) -> u32 {
-    let filesystem = parse_input(input);
-    let mut directories: Vec<PathEntry> = Vec::new();
-    let mut files: Vec<PathEntry> = Vec::new();
-    for path_entry in filesystem.content {
-        if path_entry.t == EntryType::File {
-            files.push(path_entry);
-        } else {
-            directories.push(path_entry);
-        }
-    }
-    let filesystem_size = 70000000;
-    let mut size_used = 0;
-    let mut possible_delete: Vec<PathEntry> = Vec::new();
-    let mut directories_after: Vec<PathEntry> = Vec::new();
-    for mut d in directories {
-        let mut sum = 0;
-        for f in &files {
-            if f.path.starts_with(&d.path) {
-                sum += f.size;
+enum Query {
+    Tree,
+    Largest(usize),
+    Between(u64, u64),
+    Extensions,
+    Free,
+    Transcript,
+    ExportDir(String),
+    ExportTar(String),
+    Shell,
+}
+
+const USAGE: &str = "\
+usage: advent_of_code_2022 7 [<query>]... [--human] [<file> | --from-dir <dir>]
+queries:
+  --tree [--sort name|size] [--depth <n>]  list the filesystem
+  --largest <n>                            the n largest directories
+  --between <min> <max>                    directories within a size range
+  --extensions                             total size per file extension
+  --free [--disk-size <n>] [--required <n>]
+                                           smallest directory to delete
+  --transcript                             print a terminal session exploring it
+  --export-dir <dir>                       recreate it with sparse files
+  --export-tar <file>                      write it as a tar archive
+  --shell                                  explore it interactively";
+
+// Runs the queries in the order given against the filesystem reconstructed
+// from the puzzle input, the given terminal session or a real directory.
+pub fn run(options: &[String]) {
+    if options.is_empty() {
+        solve();
+        return;
+    }
+    let mut queries: Vec<Query> = Vec::new();
+    let mut tree_options = TreeOptions::default();
+    let mut disk_size = DISK_SIZE;
+    let mut required = REQUIRED_SPACE;
+    let mut path: Option<&str> = None;
+    let mut source_dir: Option<&str> = None;
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        match option.as_str() {
+            "--tree" => queries.push(Query::Tree),
+            "--largest" => queries.push(Query::Largest(next_number(&mut iter, option))),
+            "--between" => {
+                let min = next_number(&mut iter, option);
+                let max = next_number(&mut iter, option);
+                queries.push(Query::Between(min, max));
+            },
+            "--extensions" => queries.push(Query::Extensions),
+            "--free" => queries.push(Query::Free),
+            "--transcript" => queries.push(Query::Transcript),
+            "--export-dir" => queries.push(Query::ExportDir(next_path(&mut iter, option).to_string())),
+            "--export-tar" => queries.push(Query::ExportTar(next_path(&mut iter, option).to_string())),
+            "--shell" => queries.push(Query::Shell),
+            "--from-dir" => source_dir = Some(next_path(&mut iter, option)),
+            "--disk-size" => disk_size = next_number(&mut iter, option),
+            "--required" => required = next_number(&mut iter, option),
+            "--human" => tree_options.human = true,
+            "--sort" => {
+                tree_options.sort = match iter.next().map(|s| s.as_str()) {
+                    Some("name") => SortOrder::Name,
+                    Some("size") => SortOrder::Size,
+                    _ => {
+                        println!("--sort needs \"name\" or \"size\"");
+                        process::exit(1);
+                    }
+                }
+            },
+            "--depth" => tree_options.max_depth = Some(next_number(&mut iter, option)),
+            _ if path.is_none() && !option.starts_with("--") => path = Some(option),
+            _ => {
+                println!("Unexpected argument: \"{}\"", option);
+                process::exit(1);
             }
         }
-        d.size = sum;
-        directories_after.push(d);
     }
-    let mut directories_after_2: Vec<PathEntry> = Vec::new();
-    for d in directories_after {
-        if d.path == "/" {
-            size_used = d.size;
-        }
-        directories_after_2.push(d);
+    if queries.is_empty() {
+        println!("{}", USAGE);
+        process::exit(1);
     }
-    println!("{}", size_used);
-    let needed_space = 30000000 - (filesystem_size - size_used);
-    println!("{}", filesystem_size - size_used);
-    println!("{}", needed_space);
-    for d in directories_after_2 {
-        if d.size >= needed_space {
-            possible_delete.push(d);
+    let filesystem = match (path, source_dir) {
+        (Some(_), Some(_)) => {
+            println!("Give either a terminal session or --from-dir, not both");
+            process::exit(1);
+        },
+        (_, Some(dir)) => match Filesystem::from_dir(Path::new(dir)) {
+            Ok((filesystem, skipped)) => {
+                for path in skipped {
+                    println!("Warning: skipped {}", path);
+                }
+                filesystem
+            },
+            Err(e) => {
+                println!("Error reading {}: {}", dir, e);
+                process::exit(1);
+            }
+        },
+        (path, None) => parse_or_exit(path.unwrap_or("src/seven/input.txt")),
+    };
+    let size = |size: u64| if tree_options.human { human_size(size) } else { size.to_string() };
+    for query in queries {
+        match query {
+            Query::Tree => print!("{}", filesystem.render_tree(&tree_options)),
+            Query::Largest(n) => {
+                for id in filesystem.largest_directories(n) {
+                    println!("{}\t{}", size(filesystem.nodes[id].size), filesystem.path(id));
+                }
+            },
+            Query::Between(min, max) => {
+                for id in filesystem.directories_between(min, max) {
+                    println!("{}\t{}", size(filesystem.nodes[id].size), filesystem.path(id));
+                }
+            },
+            Query::Extensions => {
+                for (extension, total) in filesystem.size_by_extension() {
+                    let extension = if extension.is_empty() { "(none)".to_string() } else { format!(".{}", extension) };
+                    println!("{}\t{}", size(total), extension);
+                }
+            },
+            Query::Free => match filesystem.smallest_to_free(disk_size, required) {
+                FreeSpace::Enough => println!("Enough free space, nothing needs to be deleted"),
+                FreeSpace::Delete(id) => println!("{}\t{}", size(filesystem.nodes[id].size), filesystem.path(id)),
+                FreeSpace::Impossible => println!("No single directory frees enough space"),
+            },
+            Query::Transcript => print!("{}", filesystem.transcript()),
+            Query::ExportDir(target) => {
+                if let Err(e) = filesystem.export_dir(Path::new(&target)) {
+                    println!("Error exporting to {}: {}", target, e);
+                    process::exit(1);
+                }
+            },
+            Query::ExportTar(target) => {
+                let result = File::create(&target).and_then(|f| filesystem.write_tar(&mut BufWriter::new(f)));
+                if let Err(e) = result {
+                    println!("Error writing {}: {}", target, e);
+                    process::exit(1);
+                }
+            },
+            Query::Shell => {
+                if let Err(e) = shell::run_shell(&filesystem, io::stdin().lock(), &mut io::stdout()) {
+                    println!("Error in shell: {}", e);
+                    process::exit(1);
+                }
+            },
         }
     }
-    let res = possible_delete.iter().min().unwrap();
-    println!("{}", res.size);
-    res.size
-}
-
-pub fn solve() {
-    println!("Solution for part 1 {}", solve_part1("src/seven/input.txt"));
-    println!("Solution for part 2 {}", solve_part2("src/seven/input.txt"));
 }
 
 #[cfg(test)]
@@ -200,15 +574,227 @@ mod test {
 
     #[test]
     fn test_sample_part1() {
-        let expected: u32= 95437;
-        let output = solve_part1("src/seven/sample-input.txt");
+        let expected: u64= 95437;
+        let output = solve_part1(&parse_input("src/seven/sample-input.txt").unwrap());
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_sample_part2() {
-        let expected: u32 = 24933642;
-        let output = solve_part2("src/seven/sample-input.txt");
+        let expected = Ok(Some(24933642));
+        let output = solve_part2(&parse_input("src/seven/sample-input.txt").unwrap());
         assert_eq!(expected, output);
     }
+
+    #[test]
+    fn test_missing_session() {
+        let error = parse_input("src/seven/no-such-input.txt").err().unwrap();
+        assert_eq!(io::ErrorKind::NotFound, error.kind());
+    }
+
+    #[test]
+    fn test_render_tree_like_puzzle() {
+        let expected = "\
+- / (dir)
+  - a (dir)
+    - e (dir)
+      - i (file, size=584)
+    - f (file, size=29116)
+    - g (file, size=2557)
+    - h.lst (file, size=62596)
+  - b.txt (file, size=14848514)
+  - c.dat (file, size=8504156)
+  - d (dir)
+    - d.ext (file, size=5626152)
+    - d.log (file, size=8033020)
+    - j (file, size=4060174)
+    - k (file, size=7214296)
+";
+        let filesystem = parse_input("src/seven/sample-input.txt").unwrap();
+        assert_eq!(expected, filesystem.render_tree(&TreeOptions::default()));
+    }
+
+    #[test]
+    fn test_render_tree_by_size_limited() {
+        let expected = "\
+- / (dir)
+  - d (dir)
+  - b.txt (file, size=14M)
+  - c.dat (file, size=8.1M)
+  - a (dir)
+";
+        let filesystem = parse_input("src/seven/sample-input.txt").unwrap();
+        let options = TreeOptions { sort: SortOrder::Size, max_depth: Some(1), human: true };
+        assert_eq!(expected, filesystem.render_tree(&options));
+    }
+
+    #[test]
+    fn test_largest_directories() {
+        let filesystem = parse_input("src/seven/sample-input.txt").unwrap();
+        let paths: Vec<String> = filesystem.largest_directories(2).iter().map(|&id| filesystem.path(id)).collect();
+        assert_eq!(vec!["/", "/d/"], paths);
+        assert_eq!(4, filesystem.largest_directories(10).len());
+    }
+
+    #[test]
+    fn test_directories_between() {
+        let filesystem = parse_input("src/seven/sample-input.txt").unwrap();
+        let paths: Vec<String> = filesystem.directories_between(500, 100000).iter().map(|&id| filesystem.path(id)).collect();
+        assert_eq!(vec!["/a/", "/a/e/"], paths);
+    }
+
+    #[test]
+    fn test_size_by_extension() {
+        let filesystem = parse_input("src/seven/sample-input.txt").unwrap();
+        let expected = vec![
+            ("txt".to_string(), 14848514),
+            ("".to_string(), 584 + 29116 + 2557 + 4060174 + 7214296),
+            ("dat".to_string(), 8504156),
+            ("log".to_string(), 8033020),
+            ("ext".to_string(), 5626152),
+            ("lst".to_string(), 62596),
+        ];
+        assert_eq!(expected, filesystem.size_by_extension());
+    }
+
+    #[test]
+    fn test_smallest_to_free() {
+        let filesystem = parse_input("src/seven/sample-input.txt").unwrap();
+        let d = match filesystem.smallest_to_free(DISK_SIZE, REQUIRED_SPACE) {
+            FreeSpace::Delete(d) => d,
+            other => panic!("expected a directory to delete, got {:?}", other),
+        };
+        assert_eq!("/d/", filesystem.path(d));
+        // with a bigger disk deleting the small `e` is already enough
+        let e = match filesystem.smallest_to_free(78381000, REQUIRED_SPACE) {
+            FreeSpace::Delete(e) => e,
+            other => panic!("expected a directory to delete, got {:?}", other),
+        };
+        assert_eq!("/a/e/", filesystem.path(e));
+        // a disk smaller than the files on it needs all of `required` freed,
+        // more than there is in total
+        assert_eq!(FreeSpace::Impossible, filesystem.smallest_to_free(1000, 60000000));
+    }
+
+    #[test]
+    fn test_enough_free_space() {
+        let filesystem = parse_input("src/seven/sample-input.txt").unwrap();
+        assert_eq!(FreeSpace::Enough, filesystem.smallest_to_free(DISK_SIZE, 20000000));
+        assert_eq!(FreeSpace::Enough, filesystem.smallest_to_free(DISK_SIZE, 0));
+        assert_eq!(FreeSpace::Impossible, filesystem.smallest_to_free(DISK_SIZE, DISK_SIZE + 1));
+        // a full disk needs the whole requirement freed
+        let root = filesystem.smallest_to_free(1, 40000000);
+        assert_eq!(FreeSpace::Delete(ROOT), root);
+    }
+
+    #[test]
+    fn test_large_sizes() {
+        let session = "\
+$ cd /
+$ ls
+dir a
+5000000000 big
+$ cd a
+$ ls
+18446744073709551615 huge
+";
+        let (filesystem, warnings) = parse_session(session);
+        assert!(warnings.is_empty());
+        assert_eq!(u64::MAX, filesystem.nodes[ROOT].size);
+        let big = filesystem.nodes.iter().find(|n| n.name == "big").unwrap();
+        assert_eq!(5000000000, big.size);
+    }
+
+    #[test]
+    fn test_repeated_ls_and_cd_root() {
+        let session = "\
+$ cd /
+$ ls
+dir a
+10 b
+$ cd /
+$ ls
+dir a
+10 b
+$ cd a
+$ ls
+5 c
+$ cd /
+$ cd a
+$ ls
+5 c
+";
+        let (filesystem, warnings) = parse_session(session);
+        assert_eq!(Vec::<ParseWarning>::new(), warnings);
+        assert_eq!(4, filesystem.nodes.len());
+        assert_eq!(15, filesystem.nodes[ROOT].size);
+    }
+
+    #[test]
+    fn test_absolute_cd() {
+        let session = "\
+$ cd /a/b
+$ ls
+7 c
+$ cd /a
+$ ls
+dir b
+3 d
+$ cd b/../b/.
+$ ls
+7 c
+";
+        let (filesystem, warnings) = parse_session(session);
+        assert_eq!(Vec::<ParseWarning>::new(), warnings);
+        let c = filesystem.nodes.iter().position(|n| n.name == "c").unwrap();
+        assert_eq!("/a/b/c", filesystem.path(c));
+        assert_eq!(10, filesystem.nodes[ROOT].size);
+        assert_eq!(5, filesystem.nodes.len());
+    }
+
+    #[test]
+    fn test_warnings() {
+        let session = "\
+oops
+$ cd ..
+$ ls
+dir a
+12 a
+x y
+1 2 3
+$ pwd
+/
+$ cd a
+3 f
+$ cd a
+";
+        let (filesystem, warnings) = parse_session(session);
+        let lines: Vec<usize> = warnings.iter().map(|w| w.line).collect();
+        assert_eq!(vec![1, 2, 5, 6, 7, 8, 9, 11], lines);
+        assert_eq!("line 2: \"cd ..\" goes above the root", warnings[1].to_string());
+        assert_eq!("line 8: unrecognized command \"$ pwd\"", warnings[5].to_string());
+        // `cd a` into the directory and then one level deeper, which creates
+        // a new directory rather than a duplicate
+        assert_eq!(3, filesystem.nodes.len());
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!("584", human_size(584));
+        assert_eq!("28K", human_size(29116));
+        assert_eq!("2.5K", human_size(2557));
+        assert_eq!("46M", human_size(48381165));
+    }
+
+    #[test]
+    fn test_sample_tree() {
+        let filesystem = parse_input("src/seven/sample-input.txt").unwrap();
+        let e = filesystem.nodes.iter().position(|n| n.name == "e").unwrap();
+        assert_eq!("/a/e/", filesystem.path(e));
+        assert_eq!(584, filesystem.nodes[e].size);
+        let a = filesystem.nodes[e].parent.unwrap();
+        assert_eq!(94853, filesystem.nodes[a].size);
+        assert_eq!(48381165, filesystem.nodes[ROOT].size);
+        assert_eq!(4, filesystem.nodes[ROOT].children.len());
+    }
 }
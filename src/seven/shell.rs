@@ -0,0 +1,308 @@
+use std::io::{self, BufRead, Write};
+
+use super::{human_size, EntryType, Filesystem, SortOrder, TreeOptions, ROOT};
+
+const HELP: &str = "\
+commands:
+  pwd                                 print the current directory
+  cd [<path>]                         change directory, the root if no path
+  ls [<path>]                         list a directory like the puzzle input
+  tree [<path>]                       list everything below a directory
+  du [-s] [-h] [<path>]               directory sizes, only the total with -s
+  find [<path>] [-name <pattern>] [-type f|d] [-size [+|-]<n>]
+                                      search by name (* and ? wildcards),
+                                      type and size in bytes
+  help                                show this help
+  exit                                leave the shell";
+
+// Whether `name` matches a shell style pattern where `*` stands for any
+// number of characters and `?` for exactly one.
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..])),
+        (Some('?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+enum SizeFilter {
+    Above(u64),
+    Below(u64),
+    Exactly(u64),
+}
+
+impl SizeFilter {
+    fn parse(s: &str) -> Result<SizeFilter, String> {
+        let number = |n: &str| n.parse::<u64>().map_err(|_| format!("find: invalid size \"{}\"", s));
+        if let Some(n) = s.strip_prefix('+') {
+            Ok(SizeFilter::Above(number(n)?))
+        } else if let Some(n) = s.strip_prefix('-') {
+            Ok(SizeFilter::Below(number(n)?))
+        } else {
+            Ok(SizeFilter::Exactly(number(s)?))
+        }
+    }
+
+    fn matches(&self, size: u64) -> bool {
+        match self {
+            SizeFilter::Above(n) => size > *n,
+            SizeFilter::Below(n) => size < *n,
+            SizeFilter::Exactly(n) => size == *n,
+        }
+    }
+}
+
+// Walks the filesystem read-only: unlike the parser, changing into a missing
+// directory is an error here rather than creating it.
+struct Shell<'a> {
+    filesystem: &'a Filesystem,
+    cwd: usize,
+}
+
+impl<'a> Shell<'a> {
+    fn new(filesystem: &'a Filesystem) -> Shell<'a> {
+        Shell { filesystem, cwd: ROOT }
+    }
+
+    fn resolve(&self, target: &str) -> Result<usize, String> {
+        let mut current = if target.starts_with('/') { ROOT } else { self.cwd };
+        for component in target.split('/').filter(|c| !c.is_empty()) {
+            if self.filesystem.nodes[current].t != EntryType::Dir {
+                return Err(format!("{}: not a directory", self.filesystem.path(current)));
+            }
+            current = match component {
+                "." => current,
+                ".." => self.filesystem.nodes[current].parent.unwrap_or(ROOT),
+                name => match self.filesystem.child(current, name) {
+                    Some(id) => id,
+                    None => return Err(format!("{}: no such file or directory", target)),
+                },
+            };
+        }
+        Ok(current)
+    }
+
+    fn resolve_dir(&self, target: &str) -> Result<usize, String> {
+        let id = self.resolve(target)?;
+        if self.filesystem.nodes[id].t != EntryType::Dir {
+            return Err(format!("{}: not a directory", target));
+        }
+        Ok(id)
+    }
+
+    // `id` and everything below it, depth first and by name.
+    fn descendants(&self, id: usize) -> Vec<usize> {
+        let mut result = vec![id];
+        for child in self.filesystem.sorted_children(id, SortOrder::Name) {
+            result.extend(self.descendants(child));
+        }
+        result
+    }
+
+    fn ls(&self, target: Option<&str>) -> Result<String, String> {
+        let id = self.resolve(target.unwrap_or("."))?;
+        let node = &self.filesystem.nodes[id];
+        if node.t == EntryType::File {
+            return Ok(format!("{} {}\n", node.size, node.name));
+        }
+        let mut output = String::new();
+        for child in self.filesystem.sorted_children(id, SortOrder::Name) {
+            let child = &self.filesystem.nodes[child];
+            match child.t {
+                EntryType::Dir => output.push_str(&format!("dir {}\n", child.name)),
+                EntryType::File => output.push_str(&format!("{} {}\n", child.size, child.name)),
+            }
+        }
+        Ok(output)
+    }
+
+    fn du(&self, args: &[&str]) -> Result<String, String> {
+        let mut summary = false;
+        let mut human = false;
+        let mut target: Option<&str> = None;
+        for &arg in args {
+            match arg {
+                "-s" => summary = true,
+                "-h" => human = true,
+                "-sh" | "-hs" => {
+                    summary = true;
+                    human = true;
+                },
+                _ if target.is_none() && !arg.starts_with('-') => target = Some(arg),
+                _ => return Err(format!("du: unexpected argument \"{}\"", arg)),
+            }
+        }
+        let id = self.resolve(target.unwrap_or("."))?;
+        let mut entries = if summary || self.filesystem.nodes[id].t == EntryType::File {
+            vec![id]
+        } else {
+            self.descendants(id).into_iter().filter(|&d| self.filesystem.nodes[d].t == EntryType::Dir).collect()
+        };
+        // like du, every directory comes after its contents
+        entries.reverse();
+        let mut output = String::new();
+        for entry in entries {
+            let size = self.filesystem.nodes[entry].size;
+            let size = if human { human_size(size) } else { size.to_string() };
+            output.push_str(&format!("{}\t{}\n", size, self.filesystem.path(entry)));
+        }
+        Ok(output)
+    }
+
+    fn find(&self, args: &[&str]) -> Result<String, String> {
+        let mut target: Option<&str> = None;
+        let mut pattern: Option<Vec<char>> = None;
+        let mut entry_type: Option<EntryType> = None;
+        let mut size: Option<SizeFilter> = None;
+        let mut iter = args.iter();
+        while let Some(&arg) = iter.next() {
+            let mut value = || iter.next().copied().ok_or(format!("find: {} needs a value", arg));
+            match arg {
+                "-name" => pattern = Some(value()?.chars().collect()),
+                "-type" => entry_type = match value()? {
+                    "f" => Some(EntryType::File),
+                    "d" => Some(EntryType::Dir),
+                    other => return Err(format!("find: unknown type \"{}\"", other)),
+                },
+                "-size" => size = Some(SizeFilter::parse(value()?)?),
+                _ if target.is_none() && !arg.starts_with('-') => target = Some(arg),
+                _ => return Err(format!("find: unexpected argument \"{}\"", arg)),
+            }
+        }
+        let id = self.resolve(target.unwrap_or("."))?;
+        let mut output = String::new();
+        for entry in self.descendants(id) {
+            let node = &self.filesystem.nodes[entry];
+            let name: Vec<char> = node.name.chars().collect();
+            if pattern.as_ref().is_some_and(|p| !glob_match(p, &name))
+                || entry_type.as_ref().is_some_and(|t| *t != node.t)
+                || size.as_ref().is_some_and(|s| !s.matches(node.size)) {
+                continue;
+            }
+            output.push_str(&self.filesystem.path(entry));
+            output.push('\n');
+        }
+        Ok(output)
+    }
+
+    fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["help"] => Ok(format!("{}\n", HELP)),
+            ["pwd"] => Ok(format!("{}\n", self.filesystem.path(self.cwd))),
+            ["cd"] => {
+                self.cwd = ROOT;
+                Ok(String::new())
+            },
+            ["cd", target] => {
+                self.cwd = self.resolve_dir(target)?;
+                Ok(String::new())
+            },
+            ["ls"] => self.ls(None),
+            ["ls", target] => self.ls(Some(target)),
+            ["tree", rest @ ..] if rest.len() <= 1 => {
+                let id = self.resolve(rest.first().copied().unwrap_or("."))?;
+                let mut output = String::new();
+                self.filesystem.render_node(id, 0, &TreeOptions::default(), &mut output);
+                Ok(output)
+            },
+            ["du", args @ ..] => self.du(args),
+            ["find", args @ ..] => self.find(args),
+            [command, ..] => Err(format!("{}: unknown command, try \"help\"", command)),
+        }
+    }
+}
+
+// Reads commands from `input` until it ends or the user types `exit`. The
+// prompt shows the current directory.
+pub fn run_shell<R: BufRead, W: Write>(filesystem: &Filesystem, input: R, output: &mut W) -> io::Result<()> {
+    let mut shell = Shell::new(filesystem);
+    let mut lines = input.lines();
+    loop {
+        write!(output, "{}$ ", filesystem.path(shell.cwd))?;
+        output.flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let line = line.trim();
+        if line == "exit" || line == "quit" {
+            break;
+        }
+        match shell.execute(line) {
+            Ok(result) => write!(output, "{}", result)?,
+            Err(message) => writeln!(output, "{}", message)?,
+        }
+    }
+    writeln!(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::parse_input;
+
+    fn sample() -> Filesystem {
+        parse_input("src/seven/sample-input.txt").unwrap()
+    }
+
+    #[test]
+    fn test_cd_ls() {
+        let filesystem = sample();
+        let mut shell = Shell::new(&filesystem);
+        assert_eq!("dir e\n29116 f\n2557 g\n62596 h.lst\n", shell.execute("ls a").unwrap());
+        shell.execute("cd a/e").unwrap();
+        assert_eq!("/a/e/\n", shell.execute("pwd").unwrap());
+        assert_eq!("584 i\n", shell.execute("ls").unwrap());
+        shell.execute("cd ../../d").unwrap();
+        assert_eq!("/d/\n", shell.execute("pwd").unwrap());
+        assert_eq!(Err("x: no such file or directory".to_string()), shell.execute("cd x"));
+        assert_eq!(Err("/b.txt: not a directory".to_string()), shell.execute("cd /b.txt"));
+        assert_eq!("/d/\n", shell.execute("pwd").unwrap());
+        shell.execute("cd").unwrap();
+        assert_eq!("/\n", shell.execute("pwd").unwrap());
+    }
+
+    #[test]
+    fn test_du() {
+        let filesystem = sample();
+        let mut shell = Shell::new(&filesystem);
+        assert_eq!("584\t/a/e/\n94853\t/a/\n", shell.execute("du a").unwrap());
+        assert_eq!("46M\t/\n", shell.execute("du -sh").unwrap());
+        assert_eq!("584\t/a/e/i\n", shell.execute("du /a/e/i").unwrap());
+    }
+
+    #[test]
+    fn test_find() {
+        let filesystem = sample();
+        let mut shell = Shell::new(&filesystem);
+        assert_eq!("/d/d.ext\n/d/d.log\n", shell.execute("find -name d.*").unwrap());
+        assert_eq!("/\n/a/\n/a/e/\n/d/\n", shell.execute("find / -type d").unwrap());
+        assert_eq!("/b.txt\n/c.dat\n/d/d.log\n", shell.execute("find -type f -size +8000000").unwrap());
+        assert_eq!("/a/e/\n/a/e/i\n/a/g\n", shell.execute("find a -size -3000 -name ?").unwrap());
+        assert!(shell.execute("find -type x").is_err());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        let matches = |p: &str, n: &str| glob_match(&p.chars().collect::<Vec<_>>(), &n.chars().collect::<Vec<_>>());
+        assert!(matches("*", ""));
+        assert!(matches("*.txt", "b.txt"));
+        assert!(matches("h.?s*", "h.lst"));
+        assert!(!matches("*.txt", "b.dat"));
+        assert!(!matches("?", ""));
+    }
+
+    #[test]
+    fn test_run_shell() {
+        let filesystem = sample();
+        let input = "cd a\nls e\nnope\nexit\nls\n";
+        let mut output: Vec<u8> = Vec::new();
+        run_shell(&filesystem, input.as_bytes(), &mut output).unwrap();
+        let expected = "/$ /a/$ 584 i\n/a/$ nope: unknown command, try \"help\"\n/a/$ \n";
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -1,40 +1,312 @@
-use std::fs;
-use itertools::Itertools;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::process;
+
+use crate::cli::next_number;
 
 fn parse_input(input_file: &str) -> String {
-    let content = fs::read_to_string(input_file).unwrap();
-    content
+    fs::read_to_string(input_file).unwrap()
 }
 
-fn solve_part1(input: &str) -> u32 {
-    let parsed_input = parse_input(input);
-    let parsed_vec: Vec<char> = parsed_input.chars().collect();
-    let length = parsed_vec.len();
-    for i in 0..length-4 {
-        if parsed_vec[i..i+4] == parsed_vec[i..i+4].into_iter().unique().copied().collect::<Vec<char>>() {
-            println!("{}", i);
-            return (i+4) as u32;
+// There are only 256 byte values, so no longer run of distinct bytes exists.
+const MAX_WINDOW: usize = 256;
+
+// Watches a stream one byte at a time for the first run of `window`
+// consecutive distinct bytes. Keeps a count per byte value, the number of
+// distinct values in the current window and the window itself in a ring
+// buffer, so memory stays constant however long the stream is.
+struct MarkerDetector {
+    window: usize,
+    counts: [usize; 256],
+    distinct: usize,
+    ring: Vec<u8>,
+    slot: usize,
+    position: usize,
+    marker: Option<usize>,
+}
+
+impl MarkerDetector {
+    fn new(window: usize) -> MarkerDetector {
+        MarkerDetector {
+            window,
+            counts: [0; 256],
+            distinct: 0,
+            // a window that can never be filled needs no ring
+            ring: if window > MAX_WINDOW { Vec::new() } else { vec![0; window] },
+            slot: 0,
+            position: 0,
+            // an empty window is complete before the first byte
+            marker: if window == 0 { Some(0) } else { None },
         }
     }
-    length as u32
+
+    // Feeds the next byte and returns the marker position (the number of
+    // bytes read up to and including the marker) once it has been found.
+    fn push(&mut self, byte: u8) -> Option<usize> {
+        if !self.searching() {
+            return self.marker;
+        }
+        let slot = self.slot;
+        if self.position >= self.window {
+            let leaving = self.ring[slot] as usize;
+            self.counts[leaving] -= 1;
+            if self.counts[leaving] == 0 {
+                self.distinct -= 1;
+            }
+        }
+        if self.counts[byte as usize] == 0 {
+            self.distinct += 1;
+        }
+        self.counts[byte as usize] += 1;
+        self.ring[slot] = byte;
+        self.slot = if slot + 1 == self.window { 0 } else { slot + 1 };
+        self.position += 1;
+        if self.distinct == self.window {
+            self.marker = Some(self.position);
+        }
+        self.marker
+    }
+
+    // Whether more bytes could still turn up the marker.
+    fn searching(&self) -> bool {
+        self.marker.is_none() && self.window <= MAX_WINDOW
+    }
 }
 
-fn solve_part2(input: &str) -> u32 {
-    let parsed_input = parse_input(input);
-    let parsed_vec: Vec<char> = parsed_input.chars().collect();
-    let length = parsed_vec.len();
-    for i in 0..length-14 {
-        if parsed_vec[i..i+14] == parsed_vec[i..i+14].into_iter().unique().copied().collect::<Vec<char>>() {
-            println!("{}", i);
-            return (i+14) as u32;
+// Position right after the first `window` consecutive distinct bytes, or
+// `None` if the stream has no such run.
+fn find_marker(stream: &[u8], window: usize) -> Option<usize> {
+    let mut detector = MarkerDetector::new(window);
+    if !detector.searching() {
+        return detector.marker;
+    }
+    stream.iter().find_map(|&byte| detector.push(byte))
+}
+
+// Runs one detector per window size over the reader in a single pass and
+// stops reading as soon as every marker has been found. Line breaks are not
+// part of the signal and are skipped.
+fn find_markers<R: Read>(reader: R, windows: &[usize]) -> io::Result<Vec<Option<usize>>> {
+    let mut detectors: Vec<MarkerDetector> = windows.iter().map(|&w| MarkerDetector::new(w)).collect();
+    let mut pending = detectors.iter().filter(|d| d.searching()).count();
+    let mut reader = BufReader::with_capacity(1 << 16, reader);
+    while pending > 0 {
+        let chunk = reader.fill_buf()?;
+        if chunk.is_empty() {
+            break;
+        }
+        let length = chunk.len();
+        for &byte in chunk {
+            if byte == b'\n' || byte == b'\r' {
+                continue;
+            }
+            for detector in detectors.iter_mut().filter(|d| d.searching()) {
+                if detector.push(byte).is_some() {
+                    pending -= 1;
+                }
+            }
+            if pending == 0 {
+                break;
+            }
+        }
+        reader.consume(length);
+    }
+    Ok(detectors.iter().map(|d| d.marker).collect())
+}
+
+fn marker_name(window: usize) -> String {
+    match window {
+        PACKET_MARKER => "start-of-packet marker".to_string(),
+        MESSAGE_MARKER => "start-of-message marker".to_string(),
+        _ => format!("marker of length {}", window),
+    }
+}
+
+const PACKET_MARKER: usize = 4;
+const MESSAGE_MARKER: usize = 14;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Preamble,
+    Packet,
+    Message,
+}
+
+impl fmt::Display for FrameKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameKind::Preamble => write!(f, "preamble"),
+            FrameKind::Packet => write!(f, "packet"),
+            FrameKind::Message => write!(f, "message"),
         }
     }
-    length as u32
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Frame<'a> {
+    pub kind: FrameKind,
+    pub offset: usize,
+    pub marker: &'a [u8],
+    pub payload: &'a [u8],
+}
+
+impl fmt::Display for Frame<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>8} {:<8} [{}] {}", self.offset, self.kind,
+            String::from_utf8_lossy(self.marker), String::from_utf8_lossy(self.payload))
+    }
+}
+
+// Splits a datastream into frames. Everything before the first
+// start-of-packet marker is preamble. A packet is its marker followed by the
+// data up to the next start-of-message marker, and a message is its marker
+// followed by the data up to the next start-of-packet marker, so packets and
+// messages alternate until the stream ends. Each search starts fresh after
+// the current marker, so markers never overlap.
+struct Frames<'a> {
+    stream: &'a [u8],
+    position: usize,
+    next: FrameKind,
+}
+
+impl<'a> Frames<'a> {
+    fn new(stream: &'a [u8]) -> Frames<'a> {
+        Frames { stream, position: 0, next: FrameKind::Preamble }
+    }
+
+    // Start of the next marker of the given length at or after `from`, or
+    // the end of the stream if there is none.
+    fn next_marker(&self, from: usize, window: usize) -> usize {
+        match find_marker(&self.stream[from..], window) {
+            Some(end) => from + end - window,
+            None => self.stream.len(),
+        }
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Frame<'a>;
+
+    fn next(&mut self) -> Option<Frame<'a>> {
+        if self.position >= self.stream.len() {
+            return None;
+        }
+        let kind = self.next;
+        let offset = self.position;
+        let (marker_length, following, next) = match kind {
+            FrameKind::Preamble => (0, PACKET_MARKER, FrameKind::Packet),
+            FrameKind::Packet => (PACKET_MARKER, MESSAGE_MARKER, FrameKind::Message),
+            FrameKind::Message => (MESSAGE_MARKER, PACKET_MARKER, FrameKind::Packet),
+        };
+        let payload_start = offset + marker_length;
+        let end = self.next_marker(payload_start, following);
+        self.position = end;
+        self.next = next;
+        if kind == FrameKind::Preamble && end == offset {
+            // the stream starts right with a packet
+            return self.next();
+        }
+        Some(Frame {
+            kind,
+            offset,
+            marker: &self.stream[offset..payload_start],
+            payload: &self.stream[payload_start..end],
+        })
+    }
+}
+
+fn solve_part1(input: &str) -> Option<usize> {
+    let parsed_input = parse_input(input);
+    find_marker(parsed_input.trim_end().as_bytes(), PACKET_MARKER)
+}
+
+fn solve_part2(input: &str) -> Option<usize> {
+    let parsed_input = parse_input(input);
+    find_marker(parsed_input.trim_end().as_bytes(), MESSAGE_MARKER)
 }
 
 pub fn solve() {
-    println!("Solution for part 1 {}", solve_part1("src/six/input.txt"));
-    println!("Solution for part 2 {}", solve_part2("src/six/input.txt"));
+    match solve_part1("src/six/input.txt") {
+        Some(res) => println!("Solution for part 1 {}", res),
+        None => println!("No start-of-packet marker found"),
+    }
+    match solve_part2("src/six/input.txt") {
+        Some(res) => println!("Solution for part 2 {}", res),
+        None => println!("No start-of-message marker found"),
+    }
+}
+
+fn dump_frames(path: &str) -> io::Result<()> {
+    let mut stream: Vec<u8> = Vec::new();
+    match path {
+        "-" => io::stdin().lock().read_to_end(&mut stream)?,
+        file => File::open(file)?.read_to_end(&mut stream)?,
+    };
+    for frame in Frames::new(stream.trim_ascii_end()) {
+        println!("{}", frame);
+    }
+    Ok(())
+}
+
+// `advent_of_code_2022 6 [--window <n>]... [<file>|-]` reports the markers
+// for every window size (start-of-packet and start-of-message if none are
+// given) reading the file, or stdin for `-`, as a stream.
+// `advent_of_code_2022 6 --frames [<file>|-]` prints the decoded frames
+// instead.
+pub fn run(options: &[String]) {
+    if options.is_empty() {
+        solve();
+        return;
+    }
+    let mut windows: Vec<usize> = Vec::new();
+    let mut path: Option<&str> = None;
+    let mut frames = false;
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        if option == "--frames" {
+            frames = true;
+        } else if option == "--window" {
+            windows.push(next_number(&mut iter, option));
+        } else if path.is_none() {
+            path = Some(option);
+        } else {
+            println!("Unexpected argument: \"{}\"", option);
+            process::exit(1);
+        }
+    }
+    if frames {
+        if !windows.is_empty() {
+            println!("--frames always uses the start-of-packet and start-of-message markers");
+            process::exit(1);
+        }
+        if let Err(e) = dump_frames(path.unwrap_or("src/six/input.txt")) {
+            println!("Error reading signal: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    if windows.is_empty() {
+        windows = vec![PACKET_MARKER, MESSAGE_MARKER];
+    }
+    let markers = match path.unwrap_or("src/six/input.txt") {
+        "-" => find_markers(io::stdin().lock(), &windows),
+        file => File::open(file).and_then(|f| find_markers(f, &windows)),
+    };
+    match markers {
+        Ok(markers) => {
+            for (window, marker) in windows.iter().zip(markers) {
+                match marker {
+                    Some(position) => println!("{} after character {}", marker_name(*window), position),
+                    None => println!("No {} found", marker_name(*window)),
+                }
+            }
+        },
+        Err(e) => {
+            println!("Error reading signal: {}", e);
+            process::exit(1);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -43,15 +315,99 @@ mod test {
 
     #[test]
     fn test_sample_part1() {
-        let expected: u32= 5;
+        let expected = Some(5);
         let output = solve_part1("src/six/sample-input.txt");
         assert_eq!(expected, output);
     }
 
     #[test]
     fn test_sample_part2() {
-        let expected: u32 = 23;
+        let expected = Some(23);
         let output = solve_part2("src/six/sample-input.txt");
         assert_eq!(expected, output);
     }
+
+    #[test]
+    fn test_puzzle_examples() {
+        let examples = [
+            ("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 7, 19),
+            ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+            ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 10, 29),
+            ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26),
+        ];
+        for (stream, packet, message) in examples {
+            assert_eq!(Some(packet), find_marker(stream.as_bytes(), 4));
+            assert_eq!(Some(message), find_marker(stream.as_bytes(), 14));
+        }
+    }
+
+    #[test]
+    fn test_marker_in_last_window() {
+        assert_eq!(Some(4), find_marker(b"abcd", 4));
+        assert_eq!(Some(6), find_marker(b"aaabcd", 4));
+    }
+
+    #[test]
+    fn test_find_markers_one_pass() {
+        let stream = "mjqjpqmgbljsphdztnvjfqwrcgsmlb\n";
+        let expected = vec![Some(7), Some(19), Some(1), None];
+        let output = find_markers(stream.as_bytes(), &[4, 14, 1, 27]).unwrap();
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_find_markers_sample_file() {
+        let file = File::open("src/six/sample-input.txt").unwrap();
+        assert_eq!(vec![Some(5), Some(23)], find_markers(file, &[4, 14]).unwrap());
+    }
+
+    #[test]
+    fn test_frames() {
+        let stream = b"aababcdhiaabcdefghijklmnzzzwxyyy";
+        let expected = vec![
+            Frame { kind: FrameKind::Preamble, offset: 0, marker: b"", payload: b"aab" },
+            Frame { kind: FrameKind::Packet, offset: 3, marker: b"abcd", payload: b"hia" },
+            Frame { kind: FrameKind::Message, offset: 10, marker: b"abcdefghijklmn", payload: b"zz" },
+            Frame { kind: FrameKind::Packet, offset: 26, marker: b"zwxy", payload: b"yy" },
+        ];
+        let output: Vec<Frame> = Frames::new(stream).collect();
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_frames_without_preamble() {
+        let output: Vec<Frame> = Frames::new(b"abcdaa").collect();
+        assert_eq!(vec![Frame { kind: FrameKind::Packet, offset: 0, marker: b"abcd", payload: b"aa" }], output);
+    }
+
+    #[test]
+    fn test_frames_cover_stream() {
+        let stream = fs::read("src/six/input.txt").unwrap();
+        let stream = stream.trim_ascii_end();
+        let mut position = 0;
+        for frame in Frames::new(stream) {
+            assert_eq!(position, frame.offset);
+            position += frame.marker.len() + frame.payload.len();
+        }
+        assert_eq!(stream.len(), position);
+    }
+
+    #[test]
+    fn test_no_marker() {
+        assert_eq!(None, find_marker(b"", 4));
+        assert_eq!(None, find_marker(b"abc", 4));
+        assert_eq!(None, find_marker(b"abcabcabc", 4));
+    }
+
+    // Windows longer than the number of byte values cannot be filled with
+    // distinct bytes, whatever their size.
+    #[test]
+    fn test_impossible_window() {
+        let stream: Vec<u8> = (0..=255).chain(0..=255).collect();
+        assert_eq!(Some(256), find_marker(&stream, MAX_WINDOW));
+        assert_eq!(None, find_marker(&stream, MAX_WINDOW + 1));
+        assert_eq!(None, find_marker(&stream, 100_000_000_000_000));
+        let expected = vec![Some(4), None];
+        assert_eq!(expected, find_markers(&stream[..], &[4, usize::MAX]).unwrap());
+    }
 }
@@ -0,0 +1,302 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use super::{Instruction, REGISTER_NAMES};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OperandKind {
+    Register,
+    Value,
+    Label,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Register(usize),
+    Value(i32),
+    // the index of the instruction jumped to
+    Label(usize),
+}
+
+// The assembler only builds instructions from operands of the kinds their
+// spec asks for, so these cannot fail.
+impl Operand {
+    fn register(&self) -> usize {
+        match self {
+            Operand::Register(r) => *r,
+            _ => unreachable!("operand kinds are checked while parsing"),
+        }
+    }
+
+    fn value(&self) -> i32 {
+        match self {
+            Operand::Value(v) => *v,
+            _ => unreachable!("operand kinds are checked while parsing"),
+        }
+    }
+
+    fn label(&self) -> usize {
+        match self {
+            Operand::Label(l) => *l,
+            _ => unreachable!("operand kinds are checked while parsing"),
+        }
+    }
+}
+
+struct Spec {
+    mnemonic: &'static str,
+    operands: &'static [OperandKind],
+    build: fn(&[Operand]) -> Instruction,
+}
+
+// Every instruction the CPU knows. `addx` is the one from the puzzle and
+// always adds to X, the others name their registers. Jumps go to labels.
+const INSTRUCTIONS: [Spec; 7] = [
+    Spec { mnemonic: "noop", operands: &[], build: |_| Instruction::Noop() },
+    Spec {
+        mnemonic: "addx", operands: &[OperandKind::Value],
+        build: |o| Instruction::AddX(o[0].value()),
+    },
+    Spec {
+        mnemonic: "add", operands: &[OperandKind::Register, OperandKind::Value],
+        build: |o| Instruction::Add(o[0].register(), o[1].value()),
+    },
+    Spec {
+        mnemonic: "set", operands: &[OperandKind::Register, OperandKind::Value],
+        build: |o| Instruction::Set(o[0].register(), o[1].value()),
+    },
+    Spec {
+        mnemonic: "mov", operands: &[OperandKind::Register, OperandKind::Register],
+        build: |o| Instruction::Mov(o[0].register(), o[1].register()),
+    },
+    Spec {
+        mnemonic: "jmp", operands: &[OperandKind::Label],
+        build: |o| Instruction::Jmp(o[0].label()),
+    },
+    Spec {
+        mnemonic: "jnz", operands: &[OperandKind::Register, OperandKind::Label],
+        build: |o| Instruction::Jnz(o[0].register(), o[1].label()),
+    },
+];
+
+// The mnemonic and operands an instruction is written with.
+fn encode(instruction: &Instruction) -> (&'static str, Vec<Operand>) {
+    match *instruction {
+        Instruction::Noop() => ("noop", vec![]),
+        Instruction::AddX(v) => ("addx", vec![Operand::Value(v)]),
+        Instruction::Add(r, v) => ("add", vec![Operand::Register(r), Operand::Value(v)]),
+        Instruction::Set(r, v) => ("set", vec![Operand::Register(r), Operand::Value(v)]),
+        Instruction::Mov(to, from) => ("mov", vec![Operand::Register(to), Operand::Register(from)]),
+        Instruction::Jmp(target) => ("jmp", vec![Operand::Label(target)]),
+        Instruction::Jnz(r, target) => ("jnz", vec![Operand::Register(r), Operand::Label(target)]),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+fn is_label_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// A line of source that is not blank: the labels defined on it and the words
+// of its instruction, if it has one.
+struct SourceLine<'a> {
+    line: usize,
+    labels: Vec<&'a str>,
+    words: Vec<&'a str>,
+}
+
+// The source split into lines with comments (from `;` to the end of the
+// line) removed. Labels are written as `name:` in front of an instruction or
+// on a line of their own.
+fn tokenize(source: &str) -> Result<Vec<SourceLine<'_>>, AsmError> {
+    let mut lines = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let code = line.split(';').next().unwrap_or("");
+        let mut words: Vec<&str> = code.split_whitespace().collect();
+        let mut labels: Vec<&str> = Vec::new();
+        while let Some(label) = words.first().and_then(|w| w.strip_suffix(':')) {
+            if !is_label_name(label) {
+                return Err(AsmError { line: i + 1, message: format!("invalid label \"{}\"", label) });
+            }
+            labels.push(label);
+            words.remove(0);
+        }
+        if !labels.is_empty() || !words.is_empty() {
+            lines.push(SourceLine { line: i + 1, labels, words });
+        }
+    }
+    Ok(lines)
+}
+
+fn parse_operand(word: &str, kind: OperandKind, labels: &HashMap<&str, usize>) -> Result<Operand, String> {
+    match kind {
+        OperandKind::Register => REGISTER_NAMES.iter().position(|&name| name == word)
+            .map(Operand::Register)
+            .ok_or(format!("unknown register \"{}\"", word)),
+        OperandKind::Value => word.parse::<i32>()
+            .map(Operand::Value)
+            .map_err(|_| format!("invalid number \"{}\"", word)),
+        OperandKind::Label => labels.get(word)
+            .map(|&target| Operand::Label(target))
+            .ok_or(format!("unknown label \"{}\"", word)),
+    }
+}
+
+// Turns source text into a program. Labels are collected first, so jumps can
+// go forwards as well as backwards. The first mistake is reported with its
+// line number.
+pub fn assemble(source: &str) -> Result<Vec<Instruction>, AsmError> {
+    let lines = tokenize(source)?;
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut index = 0;
+    for source_line in &lines {
+        for &name in &source_line.labels {
+            if labels.insert(name, index).is_some() {
+                return Err(AsmError { line: source_line.line, message: format!("label \"{}\" is defined twice", name) });
+            }
+        }
+        if !source_line.words.is_empty() {
+            index += 1;
+        }
+    }
+    let mut program = Vec::with_capacity(index);
+    for source_line in &lines {
+        let Some((&mnemonic, operands)) = source_line.words.split_first() else {
+            continue;
+        };
+        let error = |message: String| AsmError { line: source_line.line, message };
+        let spec = INSTRUCTIONS.iter().find(|spec| spec.mnemonic == mnemonic)
+            .ok_or_else(|| error(format!("unknown instruction \"{}\"", mnemonic)))?;
+        if operands.len() != spec.operands.len() {
+            return Err(error(format!("{} takes {} operand(s), found {}", mnemonic, spec.operands.len(), operands.len())));
+        }
+        let operands = operands.iter().zip(spec.operands)
+            .map(|(word, &kind)| parse_operand(word, kind, &labels))
+            .collect::<Result<Vec<Operand>, String>>()
+            .map_err(error)?;
+        program.push((spec.build)(&operands));
+    }
+    Ok(program)
+}
+
+// The name of the label disassembled programs use for `target`.
+fn label_name(target: usize, program_len: usize) -> String {
+    if target >= program_len { "end".to_string() } else { format!("l{}", target) }
+}
+
+// One instruction as it is written in source, with jump targets named like
+// `disassemble` names them.
+pub fn instruction_text(instruction: &Instruction, program_len: usize) -> String {
+    let (mnemonic, operands) = encode(instruction);
+    let mut text = mnemonic.to_string();
+    for operand in operands {
+        let word = match operand {
+            Operand::Register(r) => REGISTER_NAMES[r].to_string(),
+            Operand::Value(v) => v.to_string(),
+            Operand::Label(target) => label_name(target, program_len),
+        };
+        text.push(' ');
+        text.push_str(&word);
+    }
+    text
+}
+
+// Writes a program back as source, one instruction per line. Jump targets get
+// labels named after the instruction they point at, `end` if that is past the
+// last one. A program without jumps comes out in the puzzle's own format.
+pub fn disassemble(program: &[Instruction]) -> String {
+    let targets: BTreeSet<usize> = program.iter().flat_map(|instruction| encode(instruction).1)
+        .filter_map(|operand| match operand {
+            Operand::Label(target) => Some(target),
+            _ => None,
+        })
+        .collect();
+    let mut output = String::new();
+    for (i, instruction) in program.iter().enumerate() {
+        if targets.contains(&i) {
+            output.push_str(&format!("{}:\n", label_name(i, program.len())));
+        }
+        output.push_str(&instruction_text(instruction, program.len()));
+        output.push('\n');
+    }
+    if targets.iter().any(|&target| target >= program.len()) {
+        output.push_str("end:\n");
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_assemble() {
+        let source = "\
+; count y down from 3, adding 2 to x each time
+    set y 3
+loop: addx 2   ; the puzzle instruction
+    add y -1
+    jnz y loop
+    mov z x
+done:
+";
+        let expected = vec![
+            Instruction::Set(1, 3),
+            Instruction::AddX(2),
+            Instruction::Add(1, -1),
+            Instruction::Jnz(1, 1),
+            Instruction::Mov(2, 0),
+        ];
+        assert_eq!(Ok(expected), assemble(source));
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        let error = |line: usize, message: &str| Err(AsmError { line, message: message.to_string() });
+        assert_eq!(error(2, "unknown instruction \"addxx\""), assemble("noop\naddxx 3\n"));
+        assert_eq!(error(1, "unknown instruction \"xaddx\""), assemble("xaddx 3"));
+        assert_eq!(error(1, "addx takes 1 operand(s), found 2"), assemble("addx 3 4"));
+        assert_eq!(error(1, "invalid number \"3x\""), assemble("addx 3x"));
+        assert_eq!(error(3, "unknown register \"v\""), assemble("\n\nset v 1"));
+        assert_eq!(error(1, "unknown label \"nowhere\""), assemble("jmp nowhere"));
+        assert_eq!(error(2, "label \"a\" is defined twice"), assemble("a: noop\na:"));
+        assert_eq!(error(1, "invalid label \"1a\""), assemble("1a: noop"));
+        assert_eq!("line 1: unknown label \"b\"", assemble("jmp b").unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let program = assemble("top: jnz y done\nset y 1\njmp top\ndone:").unwrap();
+        assert_eq!("l0:\njnz y end\nset y 1\njmp l0\nend:\n", disassemble(&program));
+        assert_eq!(Ok(program.clone()), assemble(&disassemble(&program)));
+    }
+
+    #[test]
+    fn test_puzzle_roundtrip() {
+        let source = fs::read_to_string("src/ten/sample-input.txt").unwrap();
+        let program = assemble(&source).unwrap();
+        assert_eq!(146, program.len());
+        assert_eq!(source, disassemble(&program));
+    }
+
+    #[test]
+    fn test_cycles() {
+        let cycles: Vec<u32> = assemble("noop\naddx 1\nadd x 1\nset x 1\nmov x y\nl: jmp l\njnz x l").unwrap()
+            .iter().map(|instruction| instruction.cycles()).collect();
+        assert_eq!(vec![1, 2, 2, 1, 1, 1, 2], cycles);
+    }
+}
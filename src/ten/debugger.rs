@@ -0,0 +1,384 @@
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use super::asm::instruction_text;
+use super::{Config, Instruction, Observer, Tick, CPU, CRT, REGISTER_NAMES};
+
+const HELP: &str = "\
+commands:
+  step [<n>]                    run n cycles, 1 if not given
+  next                          run until the current instruction is done
+  continue                      run until a breakpoint, a watch or the end
+  break cycle <n>               stop once cycle n has run
+  break pc <n>                  stop before instruction n starts
+  watch <register> <op> <n>     stop when the comparison becomes true,
+                                op is one of == != < <= > >=
+  delete <n>                    remove breakpoint or watch n
+  info                          list breakpoints and watches
+  regs                          show the registers and the instruction running
+  list                          show the program
+  crt                           show the screen drawn so far
+  help                          show this help
+  quit                          leave the debugger";
+
+// `continue` gives up after this many cycles, in case the program loops.
+const MAX_CONTINUE: u32 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn parse(s: &str) -> Option<Comparison> {
+        match s {
+            "==" => Some(Comparison::Equal),
+            "!=" => Some(Comparison::NotEqual),
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessOrEqual),
+            ">" => Some(Comparison::Greater),
+            ">=" => Some(Comparison::GreaterOrEqual),
+            _ => None,
+        }
+    }
+
+    fn holds(&self, left: i32, right: i32) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            Comparison::Equal => "==",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stop {
+    Cycle(i32),
+    Pc(usize),
+    // register, comparison, value and whether it held after the last cycle,
+    // so a watch only fires when its condition becomes true
+    Watch(usize, Comparison, i32, bool),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::Cycle(cycle) => write!(f, "break cycle {}", cycle),
+            Stop::Pc(pc) => write!(f, "break pc {}", pc),
+            Stop::Watch(register, comparison, value, _) => {
+                write!(f, "watch {} {} {}", REGISTER_NAMES[*register], comparison.symbol(), value)
+            },
+        }
+    }
+}
+
+struct Debugger {
+    cpu: CPU,
+    crt: CRT,
+    // numbered from 1 in the order they were added, deleted ones stay None
+    // so the numbers do not change
+    stops: Vec<Option<Stop>>,
+}
+
+impl Debugger {
+    fn new(program: Vec<Instruction>, config: &Config) -> Debugger {
+        Debugger { cpu: CPU::new(program), crt: CRT::new(config), stops: Vec::new() }
+    }
+
+    fn registers_text(registers: &[i32]) -> String {
+        let values: Vec<String> = REGISTER_NAMES.iter().zip(registers)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        values.join(" ")
+    }
+
+    fn tick_text(tick: &Tick) -> String {
+        format!("cycle {}: {}\n", tick.cycle, Debugger::registers_text(&tick.registers))
+    }
+
+    // Runs one cycle and returns it with the number of every breakpoint or
+    // watch that fired, or None at the end of the program.
+    fn step(&mut self) -> Option<(Tick, Vec<usize>)> {
+        let tick = self.cpu.tick()?;
+        self.crt.observe(&tick);
+        let mut fired: Vec<usize> = Vec::new();
+        for (i, stop) in self.stops.iter_mut().enumerate() {
+            let hit = match stop {
+                Some(Stop::Cycle(cycle)) => *cycle == tick.cycle,
+                Some(Stop::Pc(pc)) => self.cpu.current.is_none() && self.cpu.pc == *pc,
+                Some(Stop::Watch(register, comparison, value, held)) => {
+                    let holds = comparison.holds(self.cpu.registers[*register], *value);
+                    let hit = holds && !*held;
+                    *held = holds;
+                    hit
+                },
+                None => false,
+            };
+            if hit {
+                fired.push(i + 1);
+            }
+        }
+        Some((tick, fired))
+    }
+
+    // Runs until `done` says so, something fires or the program ends, and
+    // reports where it stopped together with the screen.
+    fn run_until<F: FnMut(&Debugger, &Tick) -> bool>(&mut self, mut done: F) -> String {
+        let mut output = String::new();
+        loop {
+            match self.step() {
+                None => {
+                    output.push_str(&format!("program ended after {} cycles\n", self.cpu.cycle));
+                    break;
+                },
+                Some((tick, fired)) => {
+                    for number in &fired {
+                        let stop = self.stops[number - 1].expect("only existing stops fire");
+                        output.push_str(&format!("stopped at {}: {}\n", number, stop));
+                    }
+                    if !fired.is_empty() || done(self, &tick) {
+                        output.push_str(&Debugger::tick_text(&tick));
+                        break;
+                    }
+                },
+            }
+        }
+        output.push_str(&format!("{}\n", self.crt));
+        output
+    }
+
+    fn add_stop(&mut self, stop: Stop) -> String {
+        self.stops.push(Some(stop));
+        format!("{}: {}\n", self.stops.len(), stop)
+    }
+
+    fn regs(&self) -> String {
+        let mut output = format!("after cycle {}: {}\n", self.cpu.cycle, Debugger::registers_text(&self.cpu.registers));
+        let len = self.cpu.program.len();
+        match self.cpu.current {
+            Some((instruction, remaining)) => {
+                let text = instruction_text(&instruction, len);
+                output.push_str(&format!("running {} ({} cycle(s) left)\n", text, remaining));
+            },
+            None => match self.cpu.program.get(self.cpu.pc) {
+                Some(instruction) => {
+                    output.push_str(&format!("next {} at {}\n", instruction_text(instruction, len), self.cpu.pc));
+                },
+                None => output.push_str("at the end of the program\n"),
+            },
+        }
+        output
+    }
+
+    fn list(&self) -> String {
+        let len = self.cpu.program.len();
+        let mut output = String::new();
+        for (i, instruction) in self.cpu.program.iter().enumerate() {
+            // the instruction running, or the next one to start
+            let current = match self.cpu.current {
+                Some(_) => i + 1 == self.cpu.pc,
+                None => i == self.cpu.pc,
+            };
+            let marker = if current { "=>" } else { "  " };
+            output.push_str(&format!("{} {:>4}  {}\n", marker, i, instruction_text(instruction, len)));
+        }
+        output
+    }
+
+    fn execute(&mut self, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |word: &str| word.parse::<i64>().map_err(|_| format!("\"{}\" is not a number", word));
+        match words.as_slice() {
+            [] => Ok(String::new()),
+            ["help"] => Ok(format!("{}\n", HELP)),
+            ["step"] => Ok(self.run_until(|_, _| true)),
+            ["step", n] => {
+                let n = number(n)?;
+                if n < 1 {
+                    return Err("step needs at least one cycle".to_string());
+                }
+                let mut count = 0;
+                Ok(self.run_until(|_, _| {
+                    count += 1;
+                    count >= n
+                }))
+            },
+            ["next"] => Ok(self.run_until(|debugger, _| debugger.cpu.current.is_none())),
+            ["continue"] => {
+                let mut count = 0;
+                let output = self.run_until(|_, _| {
+                    count += 1;
+                    count >= MAX_CONTINUE
+                });
+                if count >= MAX_CONTINUE {
+                    Ok(format!("still running after {} cycles\n{}", MAX_CONTINUE, output))
+                } else {
+                    Ok(output)
+                }
+            },
+            ["break", "cycle", n] => {
+                let cycle = number(n)?;
+                Ok(self.add_stop(Stop::Cycle(cycle.try_into().map_err(|_| format!("no cycle {}", cycle))?)))
+            },
+            ["break", "pc", n] => {
+                let pc = number(n)?;
+                match usize::try_from(pc) {
+                    Ok(pc) if pc < self.cpu.program.len() => Ok(self.add_stop(Stop::Pc(pc))),
+                    _ => Err(format!("no instruction {}", pc)),
+                }
+            },
+            ["watch", register, op, n] => {
+                let register = REGISTER_NAMES.iter().position(|name| name == register)
+                    .ok_or(format!("unknown register \"{}\"", register))?;
+                let comparison = Comparison::parse(op).ok_or(format!("unknown comparison \"{}\"", op))?;
+                let value = number(n)?;
+                let value = i32::try_from(value).map_err(|_| format!("{} is out of range", value))?;
+                let held = comparison.holds(self.cpu.registers[register], value);
+                Ok(self.add_stop(Stop::Watch(register, comparison, value, held)))
+            },
+            ["delete", n] => {
+                let n = number(n)?;
+                match usize::try_from(n).ok().and_then(|n| self.stops.get_mut(n.wrapping_sub(1))) {
+                    Some(stop @ Some(_)) => {
+                        *stop = None;
+                        Ok(String::new())
+                    },
+                    _ => Err(format!("no breakpoint or watch {}", n)),
+                }
+            },
+            ["info"] => {
+                let mut output = String::new();
+                for (i, stop) in self.stops.iter().enumerate() {
+                    if let Some(stop) = stop {
+                        output.push_str(&format!("{}: {}\n", i + 1, stop));
+                    }
+                }
+                Ok(output)
+            },
+            ["regs"] => Ok(self.regs()),
+            ["list"] => Ok(self.list()),
+            ["crt"] => Ok(format!("{}\n", self.crt)),
+            [command, ..] => Err(format!("{}: unknown command, try \"help\"", command)),
+        }
+    }
+}
+
+// Reads debugger commands from `input` until it ends or the user types
+// `quit`. The prompt shows how many cycles have run.
+pub fn run_debugger<R: BufRead, W: Write>(program: Vec<Instruction>, config: &Config, input: R, output: &mut W) -> io::Result<()> {
+    let mut debugger = Debugger::new(program, config);
+    let mut lines = input.lines();
+    loop {
+        write!(output, "(cycle {}) ", debugger.cpu.cycle)?;
+        output.flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        let line = line.trim();
+        if line == "quit" || line == "exit" {
+            break;
+        }
+        match debugger.execute(line) {
+            Ok(result) => write!(output, "{}", result)?,
+            Err(message) => writeln!(output, "{}", message)?,
+        }
+    }
+    writeln!(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{asm::assemble, parse_input};
+
+    fn sample() -> Debugger {
+        Debugger::new(parse_input("src/ten/sample-input.txt"), &Config::default())
+    }
+
+    #[test]
+    fn test_step_and_regs() {
+        let mut debugger = sample();
+        let output = debugger.execute("step").unwrap();
+        assert!(output.starts_with("cycle 1: x=1 y=0 z=0 w=0\n\n#"));
+        assert_eq!("after cycle 1: x=1 y=0 z=0 w=0\nrunning addx 15 (1 cycle(s) left)\n", debugger.execute("regs").unwrap());
+        debugger.execute("next").unwrap();
+        assert_eq!("after cycle 2: x=16 y=0 z=0 w=0\nnext addx -11 at 1\n", debugger.execute("regs").unwrap());
+        debugger.execute("step 3").unwrap();
+        assert_eq!(5, debugger.cpu.cycle);
+        // the screen shows what has been drawn, the rest stays blank
+        let expected = format!("\n##..#{}\n{}", " ".repeat(35), format!("{}\n", " ".repeat(40)).repeat(5));
+        assert_eq!(expected, format!("{}\n", debugger.crt));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = sample();
+        assert_eq!("1: break cycle 20\n", debugger.execute("break cycle 20").unwrap());
+        assert_eq!("2: break pc 3\n", debugger.execute("break pc 3").unwrap());
+        let output = debugger.execute("continue").unwrap();
+        assert!(output.starts_with("stopped at 2: break pc 3\ncycle 6: x=5"));
+        assert_eq!("after cycle 6: x=11 y=0 z=0 w=0\nnext addx -3 at 3\n", debugger.execute("regs").unwrap());
+        let output = debugger.execute("continue").unwrap();
+        // during the 20th cycle x is 21, as in the puzzle
+        assert!(output.starts_with("stopped at 1: break cycle 20\ncycle 20: x=21"));
+        debugger.execute("delete 2").unwrap();
+        assert_eq!("1: break cycle 20\n", debugger.execute("info").unwrap());
+        assert!(debugger.execute("continue").unwrap().starts_with("program ended after 240 cycles\n"));
+        assert!(debugger.execute("delete 2").is_err());
+        assert!(debugger.execute("break pc 146").is_err());
+    }
+
+    #[test]
+    fn test_watch() {
+        let mut debugger = sample();
+        assert_eq!("1: watch x < 5\n", debugger.execute("watch x < 5").unwrap());
+        let output = debugger.execute("continue").unwrap();
+        assert!(output.starts_with("stopped at 1: watch x < 5\n"));
+        assert_eq!(4, debugger.cpu.register_x());
+        // it only fires again once x has been 5 or more in between
+        let output = debugger.execute("continue").unwrap();
+        assert!(output.starts_with("stopped at 1: watch x < 5\n"));
+        assert_eq!(1, debugger.cpu.register_x());
+        assert!(debugger.execute("watch v < 0").is_err());
+        assert!(debugger.execute("watch x ~ 0").is_err());
+    }
+
+    #[test]
+    fn test_endless_loop() {
+        let mut debugger = Debugger::new(assemble("l: jmp l").unwrap(), &Config::default());
+        let output = debugger.execute("continue").unwrap();
+        assert!(output.starts_with("still running after 1000000 cycles\n"));
+        assert_eq!("=>    0  jmp l0\n", debugger.execute("list").unwrap());
+    }
+
+    #[test]
+    fn test_run_debugger() {
+        let program = assemble("addx 3\naddx -5\n").unwrap();
+        let input = "break cycle 2\ncontinue\nnope\nquit\nstep\n";
+        let mut output: Vec<u8> = Vec::new();
+        run_debugger(program, &Config::default(), input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("(cycle 0) 1: break cycle 2\n(cycle 0) stopped at 1: break cycle 2\ncycle 2: x=1"));
+        assert!(output.ends_with("(cycle 2) nope: unknown command, try \"help\"\n(cycle 2) \n"));
+    }
+}
@@ -1,166 +1,538 @@
-use std::collections::VecDeque;
 use std::fmt;
-use std::fs;
-use std::str::FromStr;
-use std::num::ParseIntError;
-use lazy_static::lazy_static;
-use regex::Regex;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::process;
+use std::time::Duration;
 
-#[derive(Debug, PartialEq, Eq)]
+use crate::cli::{next_number, next_path};
+use crate::image::{self, upscale};
+
+mod asm;
+mod debugger;
+mod ocr;
+mod render;
+mod synth;
+
+use asm::{assemble, disassemble};
+use ocr::recognize;
+#[cfg(test)]
+use ocr::OcrError;
+use render::{play, screen_bits, screen_colors, Recorder};
+use synth::{parse_bitmap, synthesize, text_bitmap};
+
+// The CPU has a few registers besides the X of the puzzle, all starting at 0
+// except X, which starts at 1.
+const REGISTER_NAMES: [&str; 4] = ["x", "y", "z", "w"];
+const X: usize = 0;
+
+// Registers are indexes into REGISTER_NAMES, jump targets indexes into the
+// program. See `asm` for how each instruction is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Instruction {
     AddX(i32),
     Noop(),
+    Add(usize, i32),
+    Set(usize, i32),
+    Mov(usize, usize),
+    Jmp(usize),
+    Jnz(usize, usize),
 }
 
-#[derive(Debug, PartialEq, Eq)]
-struct CPU {
-    pub register_x: i32,
-    instructions: VecDeque<Instruction>,
+impl Instruction {
+    fn cycles(&self) -> u32 {
+        match self {
+            Instruction::Noop() | Instruction::Set(..) | Instruction::Mov(..) | Instruction::Jmp(_) => 1,
+            Instruction::AddX(_) | Instruction::Add(..) | Instruction::Jnz(..) => 2,
+        }
+    }
 }
 
-struct CRT {
-    pub screen: Vec<char>,
+// One clock cycle as seen from the outside: its number, counted from 1, and
+// the registers during it, before any instruction finishing in this cycle has
+// changed them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Tick {
+    pub cycle: i32,
+    pub registers: [i32; REGISTER_NAMES.len()],
 }
 
-impl fmt::Display for CRT {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for (i, elem) in self.screen.iter().enumerate() {
-            if i % 40 == 0 {
-                write!(f, "\n")?
-            }
-            write!(f, "{}", elem)?;
-        }
-        Ok(())
+impl Tick {
+    fn register_x(&self) -> i32 {
+        self.registers[X]
     }
 }
 
-impl CRT {
-    fn draw_pixels(&mut self, clock: &Clock) {
-        for i in 0..240_i32 {
-            let row = i / 40;
-            let sprite_pos_mid = clock.signal_at_cycle[i as usize];
-            if sprite_pos_mid + (row*40) == i || sprite_pos_mid + (row*40) + 1 == i || sprite_pos_mid + (row*40) - 1 == i {
-                self.screen[i as usize] = '#';
-            }
+// Jumps make it possible to loop forever, so a run gives up after this many
+// cycles.
+const MAX_CYCLES: i32 = 1_000_000;
+
+#[derive(Debug, PartialEq, Eq)]
+enum RunError {
+    TooManyCycles(i32),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::TooManyCycles(limit) => write!(f, "still running after {} cycles", limit),
         }
     }
 }
 
+// Anything that wants to follow an execution cycle by cycle.
+trait Observer {
+    fn observe(&mut self, tick: &Tick);
+}
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialEq, Eq)]
-struct Clock {
-    pub cycles: i32,
-    pub check_points: i32,
-    pub signal_strengths: Vec<i32>,
-    pub signal_at_cycle: Vec<i32>,
+struct CPU {
+    pub registers: [i32; REGISTER_NAMES.len()],
+    program: Vec<Instruction>,
+    // index of the next instruction to start
+    pc: usize,
+    // cycles completed so far
+    cycle: i32,
+    // the instruction being executed and the cycles it still needs
+    current: Option<(Instruction, u32)>,
 }
 
 impl CPU {
-    fn compute(&mut self, clock: &mut Clock) {
-        for instr in self.instructions.drain(..) {
-            match instr {
-                Instruction::AddX(x) => {
-                    let current_register = self.register_x;
-                    clock.update(2, current_register);
-                    self.register_x += x;
-                },
-                Instruction::Noop() => {
-                    let current_register = self.register_x;
-                    clock.update(1, current_register)
+    fn new(program: Vec<Instruction>) -> CPU {
+        let mut registers = [0; REGISTER_NAMES.len()];
+        registers[X] = 1;
+        CPU { registers, program, pc: 0, cycle: 0, current: None }
+    }
+
+    #[cfg(test)]
+    fn register_x(&self) -> i32 {
+        self.registers[X]
+    }
+
+    // Runs one cycle, starting the next instruction if the previous one is
+    // done. An instruction takes effect at the end of its last cycle. Returns
+    // None once the program counter has left the program, or when the cycle
+    // counter cannot go any higher.
+    fn tick(&mut self) -> Option<Tick> {
+        let (instruction, remaining) = match self.current.take() {
+            Some(current) => current,
+            None => {
+                let next = *self.program.get(self.pc)?;
+                self.pc += 1;
+                (next, next.cycles())
+            },
+        };
+        self.cycle = self.cycle.checked_add(1)?;
+        let tick = Tick { cycle: self.cycle, registers: self.registers };
+        if remaining > 1 {
+            self.current = Some((instruction, remaining - 1));
+        } else {
+            self.finish(instruction);
+        }
+        Some(tick)
+    }
+
+    fn finish(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::AddX(v) => self.registers[X] = self.registers[X].wrapping_add(v),
+            Instruction::Noop() => {},
+            Instruction::Add(r, v) => self.registers[r] = self.registers[r].wrapping_add(v),
+            Instruction::Set(r, v) => self.registers[r] = v,
+            Instruction::Mov(to, from) => self.registers[to] = self.registers[from],
+            Instruction::Jmp(target) => self.pc = target,
+            Instruction::Jnz(r, target) => {
+                if self.registers[r] != 0 {
+                    self.pc = target;
+                }
+            },
+        }
+    }
+
+    // Runs the program to its end, showing every cycle to all observers, or
+    // stops with an error once it has taken MAX_CYCLES cycles.
+    fn run(&mut self, observers: &mut [&mut dyn Observer]) -> Result<(), RunError> {
+        while self.cycle < MAX_CYCLES {
+            match self.tick() {
+                Some(tick) => {
+                    for observer in observers.iter_mut() {
+                        observer.observe(&tick);
+                    }
                 },
+                None => return Ok(()),
             }
         }
+        match self.program.get(self.pc) {
+            None if self.current.is_none() => Ok(()),
+            _ => Err(RunError::TooManyCycles(MAX_CYCLES)),
+        }
     }
 }
 
-impl Clock {
-    fn update(&mut self, cycles: i32, reg: i32) {
-        for _i in 0..cycles {
-            self.cycles += 1;
-            if 20 - self.cycles % 40 == 0 {
-                self.signal_strengths.push(self.cycles * reg);
-                self.check_points += 1;
-            }
-            self.signal_at_cycle.push(reg);
+// When the signal strength is checked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Checkpoints {
+    Every { first: i32, interval: i32 },
+    At(Vec<i32>),
+}
+
+impl Default for Checkpoints {
+    // during the 20th cycle and every 40 after, as in the puzzle
+    fn default() -> Checkpoints {
+        Checkpoints::Every { first: 20, interval: 40 }
+    }
+}
+
+impl Checkpoints {
+    fn contains(&self, cycle: i32) -> bool {
+        match self {
+            // a schedule starting far below zero would overflow the distance
+            Checkpoints::Every { first, interval } => {
+                cycle >= *first && cycle.checked_sub(*first).and_then(|since| since.checked_rem(*interval)) == Some(0)
+            },
+            Checkpoints::At(cycles) => cycles.contains(&cycle),
         }
     }
 }
 
+// The hardware around the CPU. The puzzle's screen is 40 by 6 pixels with a
+// sprite 3 pixels wide.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Config {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+    checkpoints: Checkpoints,
+}
 
-impl FromStr for Instruction {
-    type Err = ParseIntError;
+impl Default for Config {
+    fn default() -> Config {
+        Config { width: 40, height: 6, sprite_width: 3, checkpoints: Checkpoints::default() }
+    }
+}
+
+// Pixels that have not been drawn yet, because the program ended too early.
+const UNDRAWN: char = ' ';
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        lazy_static! {
-            static ref RE_A: Regex = Regex::new(r"addx ([-]?\d+)").unwrap();
-            static ref RE_N: Regex = Regex::new(r"noop").unwrap();
+#[allow(clippy::upper_case_acronyms)]
+struct CRT {
+    pub screen: Vec<char>,
+    width: usize,
+    sprite_width: usize,
+}
+
+impl CRT {
+    fn new(config: &Config) -> CRT {
+        CRT { screen: vec![UNDRAWN; config.width * config.height], width: config.width, sprite_width: config.sprite_width }
+    }
+
+    fn height(&self) -> usize {
+        self.screen.len().checked_div(self.width).unwrap_or(0)
+    }
+
+    // The index of the pixel drawn during `tick`, None once the screen is
+    // full.
+    fn beam(&self, tick: &Tick) -> Option<usize> {
+        let index = (tick.cycle - 1) as usize;
+        (index < self.screen.len()).then_some(index)
+    }
+
+    // Whether the sprite covers `column` while X is `x`. X is the middle of
+    // the sprite, or the left one of its two middle pixels if it is an even
+    // number of pixels wide.
+    fn sprite_covers(&self, x: i32, column: usize) -> bool {
+        let left = x as i64 - (self.sprite_width as i64 - 1) / 2;
+        left <= column as i64 && (column as i64) < left + self.sprite_width as i64
+    }
+}
+
+impl fmt::Display for CRT {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.screen.chunks(self.width.max(1)) {
+            writeln!(f)?;
+            for elem in row {
+                write!(f, "{}", elem)?;
+            }
         }
+        Ok(())
+    }
+}
 
-        if let Some(add) = RE_A
-            .captures(s)
-            .map(|cap| Instruction::AddX(cap[1].parse().unwrap()))
-        {
-            Ok(add)
+// The CRT draws one pixel per cycle, left to right and row by row, and lights
+// it if the sprite covers it. Cycles after the last pixel draw nothing.
+impl Observer for CRT {
+    fn observe(&mut self, tick: &Tick) {
+        if let Some(index) = self.beam(tick) {
+            let covered = self.sprite_covers(tick.register_x(), index % self.width);
+            self.screen[index] = if covered { '#' } else { '.' };
         }
-        else if let Some(noop) = RE_N
-            .captures(s)
-            .map(|_cap| Instruction::Noop())
-        {
-            Ok(noop)
-        } else {
-            println!("{}", s);
-            panic!();
+    }
+}
+
+// Adds up the signal strengths at the checkpoints, for part 1.
+struct SignalSum {
+    pub total: i32,
+    checkpoints: Checkpoints,
+}
+
+impl SignalSum {
+    fn new(config: &Config) -> SignalSum {
+        SignalSum { total: 0, checkpoints: config.checkpoints.clone() }
+    }
+}
+
+impl Observer for SignalSum {
+    fn observe(&mut self, tick: &Tick) {
+        if self.checkpoints.contains(tick.cycle) {
+            // a looping program can run the sum past what fits, wrap then
+            self.total = self.total.wrapping_add(tick.cycle.wrapping_mul(tick.register_x()));
         }
     }
 }
 
-fn parse_input(input_file: &str) -> VecDeque<Instruction> {
-    let full_string = fs::read_to_string(input_file).unwrap();
-    let input = full_string.lines().collect::<Vec<&str>>();
-    let instructions: VecDeque<Instruction> = input.iter().map(|line| Instruction::from_str(line).unwrap()).collect::<VecDeque<_>>();
-    instructions
+// The puzzle input is a valid program, so a mistake in it is fatal.
+fn parse_input(input_file: &str) -> Vec<Instruction> {
+    let source = fs::read_to_string(input_file).unwrap();
+    assemble(&source).unwrap_or_else(|e| panic!("{}: {}", input_file, e))
 }
 
+// Runs the program once with both the signal checks and the CRT watching.
+fn execute(program: Vec<Instruction>, config: &Config) -> Result<(SignalSum, CRT), RunError> {
+    let mut cpu = CPU::new(program);
+    let mut signal = SignalSum::new(config);
+    let mut crt = CRT::new(config);
+    cpu.run(&mut [&mut signal, &mut crt])?;
+    Ok((signal, crt))
+}
+
+// Only the tests solve the parts on their own, `solve` gets both answers from
+// one run. The puzzle programs have no jumps, so they always end.
+#[cfg(test)]
 fn solve_part1(input: &str) -> i32 {
-    let instructions = parse_input(input);
-    let mut cpu = CPU {
-        register_x : 1,
-        instructions : instructions,
-    };
-    let mut clock = Clock {
-        cycles : 0,
-        check_points : 0,
-        signal_strengths : vec![],
-        signal_at_cycle : vec![],
-    };
-    cpu.compute(&mut clock);
-    clock.signal_strengths.iter().sum()
+    execute(parse_input(input), &Config::default()).unwrap().0.total
 }
 
-fn solve_part2(input: &str) -> i32 {
-    let instructions = parse_input(input);
-    let mut cpu = CPU {
-        register_x : 1,
-        instructions : instructions,
-    };
-    let mut clock = Clock {
-        cycles : 0,
-        check_points : 0,
-        signal_strengths : vec![],
-        signal_at_cycle : vec![],
+#[cfg(test)]
+fn solve_part2(input: &str) -> Result<String, OcrError> {
+    let crt = execute(parse_input(input), &Config::default()).unwrap().1;
+    recognize(&crt.screen, crt.width)
+}
+
+fn report(program: Vec<Instruction>, config: &Config) {
+    let (signal, crt) = match execute(program, config) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("Error in part 1: {}", e);
+            println!("Error in part 2: {}", e);
+            return;
+        }
     };
-    let mut crt = CRT {
-        screen : vec!['.'; 240],
+    println!("Solution for part 1 {}", signal.total);
+    match recognize(&crt.screen, crt.width) {
+        Ok(res) => println!("Solution for part 2 {}", res),
+        Err(e) => println!("Error in part 2: {}, the screen shows{}", e, crt),
+    }
+}
+
+pub fn solve() {
+    report(parse_input("src/ten/input.txt"), &Config::default());
+}
+
+const USAGE: &str = "\
+usage: advent_of_code_2022 10 [--disassemble | --debug | <output>...] [<hardware>]... [<file>]
+       advent_of_code_2022 10 (--draw <text> | --draw-bitmap <file>) [<hardware>]...
+Runs the program in <file>, or the puzzle input, and prints both answers.
+  --disassemble       print the program as the assembler understands it instead
+  --debug             step through the program with breakpoints and watches
+  --draw <text>       print a program of addx and noop that writes <text> on the
+                      CRT in the puzzle font
+  --draw-bitmap <file>
+                      print one that draws <file>, rows of '#' and '.' like the
+                      CRT shows them
+outputs:
+  --pbm <file>        write the final screen as a black and white PBM image
+  --png <file>        write the final screen as a PNG image
+  --gif <file>        write an animated GIF of the screen being drawn
+  --animate           show the screen being drawn in the terminal
+  --scale <n>         pixels per CRT pixel in images, 8 by default
+  --delay <ms>        time between the frames of animations, 50 by default
+Animations show the sprite on the row being drawn in blue and the beam in red.
+hardware:
+  --width <n>         pixels per CRT row, 40 by default
+  --height <n>        CRT rows, 6 by default
+  --sprite <n>        sprite width in pixels, 3 by default
+  --every <first> <interval>
+                      check the signal strength during cycle <first> and every
+                      <interval> cycles after, 20 and 40 by default
+  --checkpoints <n>[,<n>]...
+                      check the signal strength during exactly these cycles";
+
+enum Target {
+    Text(String),
+    Bitmap(String),
+}
+
+// Prints the shortest program that draws `target`, after checking that it
+// does.
+fn synthesize_program(target: &Target, config: &Config) {
+    let bitmap = match target {
+        Target::Text(text) => text_bitmap(text, config),
+        Target::Bitmap(path) => match fs::read_to_string(path) {
+            Ok(source) => parse_bitmap(&source),
+            Err(e) => {
+                println!("Error reading {}: {}", path, e);
+                process::exit(1);
+            }
+        },
     };
-    cpu.compute(&mut clock);
-    crt.draw_pixels(&clock);
-    println!("{}", crt);
+    match bitmap.and_then(|bitmap| synthesize(&bitmap, config)) {
+        Ok(program) => print!("{}", disassemble(&program)),
+        Err(e) => {
+            println!("Error drawing: {}", e);
+            process::exit(1);
+        }
+    }
+}
 
-    0
+enum Output {
+    Pbm(String),
+    Png(String),
+    Gif(String),
+    Terminal,
 }
 
-pub fn solve() {
-    println!("Solution for part 1 {}", solve_part1("src/ten/input.txt"));
-    println!("Solution for part 2 {}", solve_part2("src/ten/input.txt"));
+// Runs the program once and writes or shows what the CRT drew, in the order
+// given.
+fn draw(program: Vec<Instruction>, config: &Config, outputs: &[Output], scale: usize, delay: Duration) {
+    let mut recorder = Recorder::new(config);
+    if let Err(e) = CPU::new(program).run(&mut [&mut recorder]) {
+        println!("Error in program: {}", e);
+        process::exit(1);
+    }
+    for output in outputs {
+        let result = match output {
+            Output::Pbm(target) => File::create(target)
+                .and_then(|f| image::write_pbm(&upscale(&screen_bits(&recorder.crt), scale), &mut BufWriter::new(f)))
+                .map_err(|e| (target, e)),
+            Output::Png(target) => File::create(target)
+                .and_then(|f| image::write_png(&upscale(&screen_colors(&recorder.crt), scale), &mut BufWriter::new(f)))
+                .map_err(|e| (target, e)),
+            Output::Gif(target) => {
+                let frames: Vec<_> = recorder.frames.iter().map(|frame| upscale(&frame.colors, scale)).collect();
+                // GIF delays are in hundredths of a second
+                let centiseconds = (delay.as_millis() / 10).min(u16::MAX as u128) as u16;
+                File::create(target)
+                    .and_then(|f| image::write_gif(&frames, centiseconds, &mut BufWriter::new(f)))
+                    .map_err(|e| (target, e))
+            },
+            Output::Terminal => {
+                if let Err(e) = play(&recorder.frames, delay, &mut io::stdout()) {
+                    println!("Error in animation: {}", e);
+                    process::exit(1);
+                }
+                Ok(())
+            },
+        };
+        if let Err((target, e)) = result {
+            println!("Error writing {}: {}", target, e);
+            process::exit(1);
+        }
+    }
+}
+
+pub fn run(options: &[String]) {
+    if options.is_empty() {
+        solve();
+        return;
+    }
+    let mut listing = false;
+    let mut debug = false;
+    let mut outputs: Vec<Output> = Vec::new();
+    let mut target: Option<Target> = None;
+    let mut scale: usize = 8;
+    let mut delay: u64 = 50;
+    let mut config = Config::default();
+    let mut path: Option<&str> = None;
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        match option.as_str() {
+            "--disassemble" => listing = true,
+            "--debug" => debug = true,
+            "--draw" => match iter.next() {
+                Some(text) => target = Some(Target::Text(text.to_string())),
+                None => {
+                    println!("--draw needs the text to draw");
+                    process::exit(1);
+                }
+            },
+            "--draw-bitmap" => target = Some(Target::Bitmap(next_path(&mut iter, option).to_string())),
+            "--pbm" => outputs.push(Output::Pbm(next_path(&mut iter, option).to_string())),
+            "--png" => outputs.push(Output::Png(next_path(&mut iter, option).to_string())),
+            "--gif" => outputs.push(Output::Gif(next_path(&mut iter, option).to_string())),
+            "--animate" => outputs.push(Output::Terminal),
+            "--scale" => scale = next_number(&mut iter, option),
+            "--delay" => delay = next_number(&mut iter, option),
+            "--width" => config.width = next_number(&mut iter, option),
+            "--height" => config.height = next_number(&mut iter, option),
+            "--sprite" => config.sprite_width = next_number(&mut iter, option),
+            "--every" => {
+                let first = next_number(&mut iter, option);
+                let interval = next_number(&mut iter, option);
+                config.checkpoints = Checkpoints::Every { first, interval };
+            },
+            "--checkpoints" => {
+                let cycles = iter.next().map(|list| list.split(',').map(|n| n.parse::<i32>()).collect());
+                config.checkpoints = match cycles {
+                    Some(Ok(cycles)) => Checkpoints::At(cycles),
+                    _ => {
+                        println!("--checkpoints needs a list of cycle numbers like 20,60,100");
+                        process::exit(1);
+                    }
+                }
+            },
+            _ if path.is_none() && !option.starts_with("--") => path = Some(option),
+            _ => {
+                println!("Unexpected argument: \"{}\"", option);
+                println!("{}", USAGE);
+                process::exit(1);
+            }
+        }
+    }
+    // cycles are counted from 1
+    let bad_schedule = matches!(config.checkpoints, Checkpoints::Every { first, interval } if first < 1 || interval < 1);
+    let modes = listing as usize + debug as usize + !outputs.is_empty() as usize;
+    let bad_hardware = config.width == 0 || config.height == 0 || config.sprite_width == 0 || bad_schedule;
+    if modes > 1 || target.is_some() && (modes > 0 || path.is_some()) || bad_hardware || scale == 0 {
+        println!("{}", USAGE);
+        process::exit(1);
+    }
+    if let Some(target) = target {
+        synthesize_program(&target, &config);
+        return;
+    }
+    let path = path.unwrap_or("src/ten/input.txt");
+    let program = match fs::read_to_string(path).map(|source| assemble(&source)) {
+        Ok(Ok(program)) => program,
+        Ok(Err(e)) => {
+            println!("Error in {}: {}", path, e);
+            process::exit(1);
+        },
+        Err(e) => {
+            println!("Error reading {}: {}", path, e);
+            process::exit(1);
+        }
+    };
+    if listing {
+        print!("{}", disassemble(&program));
+    } else if debug {
+        if let Err(e) = debugger::run_debugger(program, &config, io::stdin().lock(), &mut io::stdout()) {
+            println!("Error in debugger: {}", e);
+            process::exit(1);
+        }
+    } else if !outputs.is_empty() {
+        draw(program, &config, &outputs, scale, Duration::from_millis(delay));
+    } else {
+        report(program, &config);
+    }
 }
 
 #[cfg(test)]
@@ -169,30 +541,128 @@ mod test {
 
     #[test]
     fn basic_test() {
-        let instructions = VecDeque::from([Instruction::Noop(), Instruction::AddX(3), Instruction::AddX(-5)]);
-        let mut initial = CPU {
-            register_x : 1,
-            instructions: instructions
-        };
-        let mut clock = Clock {
-            cycles : 0,
-            check_points : 0,
-            signal_strengths : vec![],
-            signal_at_cycle : vec![],
-        };
+        let instructions = vec![Instruction::Noop(), Instruction::AddX(3), Instruction::AddX(-5)];
+        let mut initial = CPU::new(instructions.clone());
+        let mut signal = SignalSum::new(&Config::default());
+        let mut crt = CRT::new(&Config::default());
         let expected = CPU {
-            register_x : -1,
-            instructions : VecDeque::new(),
+            registers : [-1, 0, 0, 0],
+            program : instructions,
+            pc : 3,
+            cycle : 5,
+            current : None,
         };
-        let expected_clock = Clock {
-            cycles : 5,
-            check_points : 0,
-            signal_strengths: vec![],
-            signal_at_cycle : vec![1,1,1,4,4],
-        };
-        initial.compute(&mut clock);
-        assert_eq!(expected_clock, clock);
-        assert_eq!(expected, initial)
+        assert_eq!(Ok(()), initial.run(&mut [&mut signal, &mut crt]));
+        assert_eq!(expected, initial);
+        // too short to reach the first checkpoint
+        assert_eq!(0, signal.total);
+        // X is 1, 1, 1, 4 and 4, so the sprite covers each of the first five
+        // pixels as it is drawn
+        assert_eq!("##### ", crt.screen[..6].iter().collect::<String>());
+    }
+
+    #[test]
+    fn test_tick() {
+        let instructions = vec![Instruction::Noop(), Instruction::AddX(3), Instruction::AddX(-5)];
+        let mut cpu = CPU::new(instructions);
+        let mut ticks: Vec<(i32, i32)> = Vec::new();
+        while let Some(tick) = cpu.tick() {
+            ticks.push((tick.cycle, tick.register_x()));
+            // the register only changes after the last cycle of an addx
+            let expected_after = [1, 1, 4, 4, -1][tick.cycle as usize - 1];
+            assert_eq!(expected_after, cpu.register_x());
+        }
+        assert_eq!(vec![(1, 1), (2, 1), (3, 1), (4, 4), (5, 4)], ticks);
+        assert_eq!(None, cpu.tick());
+    }
+
+    #[test]
+    fn test_observers_share_one_run() {
+        let mut cpu = CPU::new(parse_input("src/ten/sample-input.txt"));
+        let mut signal = SignalSum::new(&Config::default());
+        let mut crt = CRT::new(&Config::default());
+        assert_eq!(Ok(()), cpu.run(&mut [&mut signal, &mut crt]));
+        assert_eq!(240, cpu.cycle);
+        assert_eq!(13140, signal.total);
+        assert_eq!(execute(parse_input("src/ten/sample-input.txt"), &Config::default()).unwrap().1.to_string(), crt.to_string());
+    }
+
+    #[test]
+    fn test_sample_crt() {
+        let crt = execute(parse_input("src/ten/sample-input.txt"), &Config::default()).unwrap().1;
+        let expected = "
+##..##..##..##..##..##..##..##..##..##..
+###...###...###...###...###...###...###.
+####....####....####....####....####....
+#####.....#####.....#####.....#####.....
+######......######......######......####
+#######.......#######.......#######.....";
+        assert_eq!(expected, crt.to_string());
+    }
+
+    // y counts down from 3 while x gets 2 added each round, then x is copied
+    // to z: jumps cost cycles like everything else.
+    #[test]
+    fn test_registers_and_jumps() {
+        let program = assemble("set y 3\nloop: addx 2\nadd y -1\njnz y loop\nmov z x\n").unwrap();
+        let mut cpu = CPU::new(program);
+        let mut cycles = 0;
+        while cpu.tick().is_some() {
+            cycles += 1;
+        }
+        assert_eq!(1 + 3 * (2 + 2 + 2) + 1, cycles);
+        assert_eq!([7, 0, 7, 0], cpu.registers);
+    }
+
+    // A loop that never ends is stopped at the cycle budget, while a program
+    // ending on its very last cycle is still fine.
+    #[test]
+    fn test_cycle_budget() {
+        let mut cpu = CPU::new(assemble("loop: noop\njmp loop\n").unwrap());
+        let mut signal = SignalSum::new(&Config::default());
+        assert_eq!(Err(RunError::TooManyCycles(MAX_CYCLES)), cpu.run(&mut [&mut signal]));
+        assert_eq!(MAX_CYCLES, cpu.cycle);
+        assert!(execute(assemble("loop: jnz x loop\n").unwrap(), &Config::default()).is_err());
+        let mut cpu = CPU::new(vec![Instruction::Noop(); MAX_CYCLES as usize]);
+        assert_eq!(Ok(()), cpu.run(&mut []));
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let every = Checkpoints::default();
+        let checked: Vec<i32> = (1..=240).filter(|&c| every.contains(c)).collect();
+        assert_eq!(vec![20, 60, 100, 140, 180, 220], checked);
+        let at = Checkpoints::At(vec![3, 5]);
+        assert_eq!(vec![3, 5], (1..=10).filter(|&c| at.contains(c)).collect::<Vec<_>>());
+        assert!(!Checkpoints::Every { first: i32::MIN, interval: 3 }.contains(i32::MAX));
+        assert!(Checkpoints::Every { first: -5, interval: 3 }.contains(1));
+        let config = Config { checkpoints: at, ..Config::default() };
+        // x is 1, 1, 1, 4, 4, -1 during the cycles
+        let program = vec![Instruction::Noop(), Instruction::AddX(3), Instruction::AddX(-5), Instruction::Noop()];
+        assert_eq!(3 + 4 * 5, execute(program, &config).unwrap().0.total);
+    }
+
+    // A program far shorter than the screen leaves the rest of it undrawn
+    // rather than failing.
+    #[test]
+    fn test_short_program() {
+        let program = vec![Instruction::Noop(), Instruction::AddX(3), Instruction::AddX(-5)];
+        let crt = execute(program, &Config::default()).unwrap().1;
+        let expected = format!("\n#####{}{}", " ".repeat(35), format!("\n{}", " ".repeat(40)).repeat(5));
+        assert_eq!(expected, crt.to_string());
+        assert!(recognize(&crt.screen, crt.width).is_err());
+    }
+
+    #[test]
+    fn test_screen_geometry() {
+        let program = vec![Instruction::Noop(), Instruction::AddX(3), Instruction::AddX(-5), Instruction::Noop()];
+        // x is 1, 1, 1, 4, 4, -1 during the six pixels
+        let config = Config { width: 3, height: 2, sprite_width: 1, ..Config::default() };
+        assert_eq!("\n.#.\n...", execute(program.clone(), &config).unwrap().1.to_string());
+        let config = Config { width: 3, height: 2, sprite_width: 4, ..Config::default() };
+        assert_eq!("\n###\n...", execute(program.clone(), &config).unwrap().1.to_string());
+        let config = Config { width: 6, height: 1, sprite_width: 2, ..Config::default() };
+        assert_eq!("\n.##.#.", execute(program, &config).unwrap().1.to_string());
     }
 
     #[test]
@@ -204,7 +674,8 @@ mod test {
 
     #[test]
     fn test_sample_part2() {
-        let expected: i32 = 0;
+        // the sample draws stripes rather than letters
+        let expected = Err(OcrError::Unknown { text: "????????".to_string(), positions: (1..=8).collect() });
         let output = solve_part2("src/ten/sample-input.txt");
         assert_eq!(expected, output);
     }
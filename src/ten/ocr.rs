@@ -0,0 +1,147 @@
+use std::fmt;
+
+pub const GLYPH_WIDTH: usize = 4;
+pub const GLYPH_HEIGHT: usize = 6;
+// every glyph is followed by one blank column, except for the few that are
+// one column wider and take it up, like Y
+pub const GLYPH_STRIDE: usize = GLYPH_WIDTH + 1;
+
+// The letters of the puzzle font we have seen, row by row. The font has more
+// of them, and answers may use those.
+const FONT: [(char, [&str; GLYPH_HEIGHT]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+// The rows of `letter` in the font, if it has one.
+pub fn glyph(letter: char) -> Option<&'static [&'static str; GLYPH_HEIGHT]> {
+    FONT.iter().find(|(c, _)| *c == letter).map(|(_, rows)| rows)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum OcrError {
+    // the screen is not GLYPH_HEIGHT pixels high
+    Height(usize),
+    // what could be read, with `?` for every letter that could not, and
+    // where those are, counted from 1
+    Unknown { text: String, positions: Vec<usize> },
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OcrError::Height(height) => {
+                write!(f, "the screen is {} pixels high, letters need {}", height, GLYPH_HEIGHT)
+            },
+            OcrError::Unknown { text, positions } => {
+                let positions: Vec<String> = positions.iter().map(|p| p.to_string()).collect();
+                write!(f, "unknown letters at {} in \"{}\"", positions.join(", "), text)
+            },
+        }
+    }
+}
+
+// Reads the letters off a screen of `width` pixels per row, with one letter
+// every GLYPH_STRIDE columns. A letter is only recognised if it matches the
+// font exactly, including the blank column after it if it has one.
+pub fn recognize(screen: &[char], width: usize) -> Result<String, OcrError> {
+    let height = screen.len().checked_div(width).unwrap_or(0);
+    if height != GLYPH_HEIGHT {
+        return Err(OcrError::Height(height));
+    }
+    let lit = |row: usize, column: usize| column < width && screen[row * width + column] == '#';
+    let mut text = String::new();
+    let mut positions: Vec<usize> = Vec::new();
+    for (i, start) in (0..width).step_by(GLYPH_STRIDE).enumerate() {
+        let letter = FONT.iter().find(|(_, rows)| {
+            rows.iter().enumerate().all(|(row, pattern)| {
+                let pattern = pattern.as_bytes();
+                (0..GLYPH_STRIDE).all(|column| (pattern.get(column) == Some(&b'#')) == lit(row, start + column))
+            })
+        });
+        match letter {
+            Some((c, _)) => text.push(*c),
+            _ => {
+                text.push('?');
+                positions.push(i + 1);
+            },
+        }
+    }
+    if positions.is_empty() {
+        Ok(text)
+    } else {
+        Err(OcrError::Unknown { text, positions })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Draws `text` the way the CRT would, one letter every GLYPH_STRIDE
+    // columns.
+    fn draw(text: &str, width: usize) -> Vec<char> {
+        let mut screen = vec!['.'; width * GLYPH_HEIGHT];
+        for (i, letter) in text.chars().enumerate() {
+            for (row, pattern) in glyph(letter).unwrap().iter().enumerate() {
+                for (column, c) in pattern.chars().enumerate() {
+                    screen[row * width + i * GLYPH_STRIDE + column] = c;
+                }
+            }
+        }
+        screen
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let alphabet: String = FONT.iter().map(|(c, _)| *c).collect();
+        let width = alphabet.len() * GLYPH_STRIDE;
+        assert_eq!(Ok(alphabet.clone()), recognize(&draw(&alphabet, width), width));
+        assert_eq!(Ok("FCJAPJRE".to_string()), recognize(&draw("FCJAPJRE", 40), 40));
+    }
+
+    #[test]
+    fn test_wide_letters() {
+        assert_eq!(Ok("HI".to_string()), recognize(&draw("HI", 10), 10));
+        assert_eq!(Ok("YAY".to_string()), recognize(&draw("YAY", 15), 15));
+        // Y takes up the blank column, so it ends where the screen does
+        assert_eq!(Ok("IY".to_string()), recognize(&draw("IY", 10), 10));
+        // and anything else there is not a letter
+        let mut screen = draw("HI", 10);
+        screen[3 * 10 + 4] = '#';
+        assert_eq!(Err(OcrError::Unknown { text: "?I".to_string(), positions: vec![1] }), recognize(&screen, 10));
+    }
+
+    #[test]
+    fn test_unknown_letters() {
+        let mut screen = draw("HELLO", 25);
+        // a stray pixel in the gap after the first L and a broken O
+        screen[2 * 25 + 14] = '#';
+        screen[5 * 25 + 21] = '.';
+        let expected = OcrError::Unknown { text: "HE?L?".to_string(), positions: vec![3, 5] };
+        assert_eq!("unknown letters at 3, 5 in \"HE?L?\"", expected.to_string());
+        assert_eq!(Err(expected), recognize(&screen, 25));
+    }
+
+    #[test]
+    fn test_wrong_height() {
+        assert_eq!(Err(OcrError::Height(1)), recognize(&['#'; 40], 40));
+        assert_eq!(Err(OcrError::Height(0)), recognize(&[], 0));
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::{self, Write};
+use std::thread;
+use std::time::Duration;
+
+use crate::grid::Grid;
+use crate::image::Rgb;
+
+use super::{Config, Observer, Tick, CRT};
+
+// Lit pixels glow green on a dark screen. Pixels the program never reached
+// stay black.
+const LIT: Rgb = [80, 255, 120];
+const DARK: Rgb = [20, 40, 25];
+const UNDRAWN: Rgb = [0, 0, 0];
+// The sprite on the row being drawn, over dark and over lit pixels, and the
+// pixel being drawn.
+const SPRITE: Rgb = [40, 70, 160];
+const SPRITE_LIT: Rgb = [140, 200, 255];
+const BEAM: Rgb = [230, 30, 30];
+
+fn pixel_color(pixel: char) -> Rgb {
+    match pixel {
+        '#' => LIT,
+        '.' => DARK,
+        _ => UNDRAWN,
+    }
+}
+
+// The screen as it is, one colour per pixel.
+pub fn screen_colors(crt: &CRT) -> Grid<Rgb> {
+    let mut colors = Grid::filled(crt.width, crt.height(), UNDRAWN);
+    for (i, &pixel) in crt.screen.iter().enumerate() {
+        colors[(i / crt.width, i % crt.width)] = pixel_color(pixel);
+    }
+    colors
+}
+
+// The lit pixels of the screen, for black and white images.
+pub fn screen_bits(crt: &CRT) -> Grid<bool> {
+    screen_colors(crt).map(|&color| color == LIT)
+}
+
+// One frame of an animation: the screen right after the pixel of `tick` was
+// drawn, with the sprite and the beam on top.
+pub struct Frame {
+    pub tick: Tick,
+    pub colors: Grid<Rgb>,
+}
+
+// Follows the CRT and keeps a frame for every pixel it draws.
+pub struct Recorder {
+    pub crt: CRT,
+    pub frames: Vec<Frame>,
+}
+
+impl Recorder {
+    pub fn new(config: &Config) -> Recorder {
+        Recorder { crt: CRT::new(config), frames: Vec::new() }
+    }
+}
+
+impl Observer for Recorder {
+    fn observe(&mut self, tick: &Tick) {
+        self.crt.observe(tick);
+        let Some(beam) = self.crt.beam(tick) else {
+            return;
+        };
+        let mut colors = screen_colors(&self.crt);
+        let row = beam / self.crt.width;
+        for column in 0..self.crt.width {
+            if self.crt.sprite_covers(tick.register_x(), column) {
+                let color = &mut colors[(row, column)];
+                *color = if *color == LIT { SPRITE_LIT } else { SPRITE };
+            }
+        }
+        colors[(row, beam % self.crt.width)] = BEAM;
+        self.frames.push(Frame { tick: *tick, colors });
+    }
+}
+
+// A frame for the terminal: the cycle and X above the screen, with every
+// pixel two characters wide so it comes out roughly square, using 24 bit
+// ANSI escape codes.
+pub fn render_ansi(frame: &Frame) -> String {
+    let mut output = format!("cycle {:>5}  x {:>5}\n", frame.tick.cycle, frame.tick.register_x());
+    for row in frame.colors.rows() {
+        for [r, g, b] in row {
+            output.push_str(&format!("\x1b[48;2;{};{};{}m  ", r, g, b));
+        }
+        output.push_str("\x1b[0m\n");
+    }
+    output
+}
+
+// Shows the frames one after the other in place, `delay` apart.
+pub fn play<W: Write>(frames: &[Frame], delay: Duration, out: &mut W) -> io::Result<()> {
+    write!(out, "\x1b[2J")?;
+    for frame in frames {
+        write!(out, "\x1b[H{}", render_ansi(frame))?;
+        out.flush()?;
+        thread::sleep(delay);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{Instruction, CPU};
+
+    // x is 1, 1, 1, 4, 4, -1 during the cycles, on a screen of 3 by 2
+    fn record() -> Recorder {
+        let program = vec![Instruction::Noop(), Instruction::AddX(3), Instruction::AddX(-5), Instruction::Noop()];
+        let config = Config { width: 3, height: 2, ..Config::default() };
+        let mut recorder = Recorder::new(&config);
+        assert_eq!(Ok(()), CPU::new(program).run(&mut [&mut recorder]));
+        recorder
+    }
+
+    #[test]
+    fn test_screen() {
+        let recorder = record();
+        let expected = Grid::from_rows(vec![vec![LIT, LIT, LIT], vec![DARK, DARK, DARK]]).unwrap();
+        assert_eq!(expected, screen_colors(&recorder.crt));
+        assert_eq!(expected.map(|&color| color == LIT), screen_bits(&recorder.crt));
+    }
+
+    #[test]
+    fn test_frames() {
+        let recorder = record();
+        assert_eq!(6, recorder.frames.len());
+        let second = &recorder.frames[1];
+        assert_eq!(2, second.tick.cycle);
+        let expected = Grid::from_rows(vec![vec![SPRITE_LIT, BEAM, SPRITE], vec![UNDRAWN; 3]]).unwrap();
+        assert_eq!(expected, second.colors);
+        // x is 4 in the fourth cycle, so only the beam shows on the second row
+        let expected = Grid::from_rows(vec![vec![LIT; 3], vec![BEAM, UNDRAWN, UNDRAWN]]).unwrap();
+        assert_eq!(expected, recorder.frames[3].colors);
+    }
+
+    #[test]
+    fn test_short_program() {
+        let mut recorder = Recorder::new(&Config::default());
+        assert_eq!(Ok(()), CPU::new(vec![Instruction::Noop()]).run(&mut [&mut recorder]));
+        assert_eq!(1, recorder.frames.len());
+        let colors = screen_colors(&recorder.crt);
+        assert_eq!(LIT, colors[(0, 0)]);
+        assert_eq!(UNDRAWN, colors[(5, 39)]);
+    }
+
+    #[test]
+    fn test_play() {
+        let recorder = record();
+        let mut output: Vec<u8> = Vec::new();
+        play(&recorder.frames[..2], Duration::ZERO, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("\x1b[2J\x1b[Hcycle     1  x     1\n\x1b[48;2;230;30;30m  "));
+        assert_eq!(2, output.matches("\x1b[H").count());
+        assert_eq!(4, output.matches("\x1b[0m\n").count());
+    }
+}
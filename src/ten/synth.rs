@@ -0,0 +1,239 @@
+use std::fmt;
+
+use crate::grid::{Grid, GridError};
+
+use super::ocr::{glyph, GLYPH_HEIGHT, GLYPH_STRIDE, GLYPH_WIDTH};
+use super::{execute, Config, Instruction, RunError, CRT};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SynthError {
+    // the bitmap does not parse, line and column counted from 1
+    Ragged(GridError),
+    InvalidPixel { line: usize, column: usize, found: char },
+    // the bitmap or the font does not fit the screen, as width by height
+    Size { expected: (usize, usize), found: (usize, usize) },
+    UnknownLetter(char),
+    // no program can draw this pixel after the ones before it, counted from 1
+    Undrawable { row: usize, column: usize },
+    // the emulator drew something else than the program was made for
+    Mismatch { row: usize, column: usize },
+    // the emulator did not get to the end of the program
+    Run(RunError),
+}
+
+impl fmt::Display for SynthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SynthError::Ragged(e) => write!(f, "{}", e),
+            SynthError::InvalidPixel { line, column, found } => {
+                write!(f, "line {}, column {}: expected '#' or '.', found {:?}", line, column, found)
+            },
+            SynthError::Size { expected: (ew, eh), found: (fw, fh) } => {
+                write!(f, "the image is {} by {} pixels, the screen {} by {}", fw, fh, ew, eh)
+            },
+            SynthError::UnknownLetter(c) => write!(f, "the font has no {:?}", c),
+            SynthError::Undrawable { row, column } => {
+                write!(f, "no program draws row {}, column {} along with the pixels before it", row, column)
+            },
+            SynthError::Mismatch { row, column } => {
+                write!(f, "the program draws row {}, column {} wrong", row, column)
+            },
+            SynthError::Run(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// A bitmap written like the CRT draws it, with '#' for lit and '.' for dark
+// pixels. Blank lines are skipped, so the CRT's own output reads back.
+pub fn parse_bitmap(source: &str) -> Result<Grid<bool>, SynthError> {
+    let mut rows: Vec<Vec<bool>> = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row = line.trim_end().chars().enumerate()
+            .map(|(column, c)| match c {
+                '#' => Ok(true),
+                '.' => Ok(false),
+                found => Err(SynthError::InvalidPixel { line: i + 1, column: column + 1, found }),
+            })
+            .collect::<Result<Vec<bool>, SynthError>>()?;
+        rows.push(row);
+    }
+    Grid::from_rows(rows).map_err(SynthError::Ragged)
+}
+
+// `text` in the puzzle font, one letter every GLYPH_STRIDE columns from the
+// left, on a screen of the configured size.
+pub fn text_bitmap(text: &str, config: &Config) -> Result<Grid<bool>, SynthError> {
+    let letters: Vec<char> = text.chars().collect();
+    // the last letter needs no blank column after it, unless it is a wide one
+    let last_width = letters.last().and_then(|&letter| glyph(letter)).map_or(GLYPH_WIDTH, |rows| rows[0].len());
+    let needed = ((letters.len() * GLYPH_STRIDE + last_width).saturating_sub(GLYPH_STRIDE), GLYPH_HEIGHT);
+    if config.height != GLYPH_HEIGHT || needed.0 > config.width {
+        return Err(SynthError::Size { expected: (config.width, config.height), found: needed });
+    }
+    let mut bitmap = Grid::filled(config.width, config.height, false);
+    for (i, &letter) in letters.iter().enumerate() {
+        let rows = glyph(letter).ok_or(SynthError::UnknownLetter(letter))?;
+        for (row, pattern) in rows.iter().enumerate() {
+            for (column, c) in pattern.chars().enumerate() {
+                bitmap[(row, i * GLYPH_STRIDE + column)] = c == '#';
+            }
+        }
+    }
+    Ok(bitmap)
+}
+
+// How a state of the search was reached: the state before it and the
+// instruction that led here.
+type Step = (usize, i32, Instruction);
+
+// Finds the shortest program of `addx` and `noop` that draws `target`, then
+// runs it to make sure.
+//
+// The search goes through the pixels in drawing order. A state is the pixel
+// about to be drawn and the X it is drawn with, between two instructions. A
+// `noop` draws one pixel, an `addx` two with the same X before changing it.
+// X values further out than the sprite reaches are all alike, so only those
+// in between are tried.
+pub fn synthesize(target: &Grid<bool>, config: &Config) -> Result<Vec<Instruction>, SynthError> {
+    if (target.width(), target.height()) != (config.width, config.height) {
+        return Err(SynthError::Size { expected: (config.width, config.height), found: (target.width(), target.height()) });
+    }
+    let pixels = target.width() * target.height();
+    let crt = CRT::new(config);
+    let reach = config.sprite_width as i32 + 1;
+    let (low, high) = (-reach, config.width as i32 + reach);
+    let values = (high - low + 1) as usize;
+    let draws = |pixel: usize, x: i32| {
+        pixel >= pixels || crt.sprite_covers(x, pixel % config.width) == target[(pixel / config.width, pixel % config.width)]
+    };
+    // the shortest way into every state, past the last pixel all in one row
+    let mut steps: Vec<Vec<Option<(usize, Step)>>> = vec![vec![None; values]; pixels + 1];
+    let mut start = vec![None; values];
+    let mut furthest = 0;
+    start[(1 - low) as usize] = Some((0, (0, 1, Instruction::Noop())));
+    steps[0] = start;
+    for pixel in 0..pixels {
+        for x in low..=high {
+            let Some((length, _)) = steps[pixel][(x - low) as usize] else {
+                continue;
+            };
+            furthest = pixel;
+            if !draws(pixel, x) {
+                continue;
+            }
+            let mut enter = |next: usize, value: i32, instruction: Instruction| {
+                let state = &mut steps[next.min(pixels)][(value - low) as usize];
+                if state.is_none_or(|(known, _)| length + 1 < known) {
+                    *state = Some((length + 1, (pixel, x, instruction)));
+                }
+            };
+            enter(pixel + 1, x, Instruction::Noop());
+            if draws(pixel + 1, x) {
+                for value in (low..=high).filter(|&value| value != x) {
+                    enter(pixel + 2, value, Instruction::AddX(value - x));
+                }
+            }
+        }
+    }
+    let end = (low..=high).filter_map(|x| steps[pixels][(x - low) as usize].map(|(length, _)| (length, x))).min();
+    let Some((_, mut x)) = end else {
+        return Err(SynthError::Undrawable { row: furthest / config.width + 1, column: furthest % config.width + 1 });
+    };
+    let mut program = Vec::new();
+    let mut pixel = pixels;
+    while pixel > 0 {
+        let (_, (before, value, instruction)) = steps[pixel][(x - low) as usize].expect("every step leads back to the start");
+        program.push(instruction);
+        pixel = before;
+        x = value;
+    }
+    program.reverse();
+    verify(&program, target, config)?;
+    Ok(program)
+}
+
+// Runs `program` and compares what the CRT draws with `target`.
+fn verify(program: &[Instruction], target: &Grid<bool>, config: &Config) -> Result<(), SynthError> {
+    let crt = execute(program.to_vec(), config).map_err(SynthError::Run)?.1;
+    for (row, column) in target.positions() {
+        if (crt.screen[row * config.width + column] == '#') != target[(row, column)] {
+            return Err(SynthError::Mismatch { row: row + 1, column: column + 1 });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::{assemble, parse_input, recognize, MAX_CYCLES};
+
+    #[test]
+    fn test_text() {
+        let config = Config::default();
+        let program = synthesize(&text_bitmap("FCJAPJRE", &config).unwrap(), &config).unwrap();
+        assert!(program.iter().all(|i| matches!(i, Instruction::Noop() | Instruction::AddX(_))));
+        let crt = execute(program, &config).unwrap().1;
+        assert_eq!(Ok("FCJAPJRE".to_string()), recognize(&crt.screen, crt.width));
+    }
+
+    #[test]
+    fn test_wide_letters() {
+        let config = Config::default();
+        // a Y at the end takes up all 40 columns
+        let program = synthesize(&text_bitmap("EHIYAHIY", &config).unwrap(), &config).unwrap();
+        let crt = execute(program, &config).unwrap().1;
+        assert_eq!(Ok("EHIYAHIY".to_string()), recognize(&crt.screen, crt.width));
+        let expected = SynthError::Size { expected: (40, 6), found: (45, 6) };
+        assert_eq!(Err(expected), text_bitmap("EHIYAHIYY", &config));
+    }
+
+    // The sample's picture comes out of a program at most as long as the
+    // sample itself.
+    #[test]
+    fn test_sample_picture() {
+        let config = Config::default();
+        let sample = parse_input("src/ten/sample-input.txt");
+        let crt = execute(sample.clone(), &config).unwrap().1;
+        let target = parse_bitmap(&crt.to_string()).unwrap();
+        let program = synthesize(&target, &config).unwrap();
+        assert!(program.len() <= sample.len());
+        assert_eq!(crt.to_string(), execute(program, &config).unwrap().1.to_string());
+    }
+
+    #[test]
+    fn test_other_hardware() {
+        let config = Config { width: 8, height: 2, sprite_width: 1, ..Config::default() };
+        let target = parse_bitmap(".#..#...\n..#....#\n").unwrap();
+        let program = synthesize(&target, &config).unwrap();
+        assert_eq!("\n.#..#...\n..#....#", execute(program, &config).unwrap().1.to_string());
+        // X only changes every other cycle, so a narrow sprite cannot draw
+        // three pixels in a row
+        let target = parse_bitmap(".#.###..\n........\n").unwrap();
+        assert_eq!(Err(SynthError::Undrawable { row: 1, column: 6 }), synthesize(&target, &config));
+        // nor can X move away from the first pixel before it is drawn
+        let target = parse_bitmap("#.......\n........\n").unwrap();
+        assert_eq!(Err(SynthError::Undrawable { row: 1, column: 1 }), synthesize(&target, &config));
+    }
+
+    #[test]
+    fn test_errors() {
+        let config = Config::default();
+        assert_eq!(Err(SynthError::UnknownLetter('Q')), text_bitmap("AQ", &config));
+        let expected = SynthError::Size { expected: (40, 6), found: (44, 6) };
+        assert_eq!(Err(expected), text_bitmap("ABCDEFGHJ", &config));
+        let expected = SynthError::InvalidPixel { line: 2, column: 3, found: 'x' };
+        assert_eq!("line 2, column 3: expected '#' or '.', found 'x'", expected.to_string());
+        assert_eq!(Err(expected), parse_bitmap("...\n..x\n"));
+        assert!(matches!(parse_bitmap("...\n..\n"), Err(SynthError::Ragged(_))));
+        let target = parse_bitmap("#.#\n").unwrap();
+        let expected = SynthError::Size { expected: (40, 6), found: (3, 1) };
+        assert_eq!(Err(expected), synthesize(&target, &config));
+        let endless = assemble("loop: jmp loop\n").unwrap();
+        let expected = SynthError::Run(RunError::TooManyCycles(MAX_CYCLES));
+        assert_eq!(Err(expected), verify(&endless, &target, &config));
+    }
+}
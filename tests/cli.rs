@@ -0,0 +1,70 @@
+// Runs the binary the way a user would, for output that is meant to be fed
+// back into it.
+
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_advent_of_code_2022")).args(args).output().unwrap();
+    assert!(output.status.success(), "{:?} failed: {}", args, String::from_utf8_lossy(&output.stdout));
+    output
+}
+
+// Output of a run that is expected to fail with exit code 1.
+fn failure(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_advent_of_code_2022")).args(args).output().unwrap();
+    assert_eq!(Some(1), output.status.code(), "{:?} did not fail", args);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn stdout(args: &[&str]) -> String {
+    String::from_utf8(run(args).stdout).unwrap()
+}
+
+// A file in the temporary directory, named after the test so that tests
+// running at the same time do not share it.
+fn temp_file(name: &str) -> String {
+    let path = env::temp_dir().join(format!("advent_of_code_2022-{}-{}", std::process::id(), name));
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_transcript_roundtrip() {
+    let transcript = stdout(&["7", "--transcript", "src/seven/sample-input.txt"]);
+    assert!(transcript.starts_with("$ cd /\n"));
+    let path = temp_file("session.txt");
+    fs::write(&path, &transcript).unwrap();
+    let tree = stdout(&["7", "--tree", &path]);
+    fs::remove_file(&path).unwrap();
+    assert!(!tree.contains("Warning"), "{}", tree);
+    assert_eq!(stdout(&["7", "--tree", "src/seven/sample-input.txt"]), tree);
+}
+
+#[test]
+fn test_missing_map() {
+    let output = failure(&["8", "--heatmap", "src/eight/missing-input.txt"]);
+    assert!(output.starts_with("Error reading src/eight/missing-input.txt: "), "{}", output);
+}
+
+#[test]
+fn test_disassemble_roundtrip() {
+    let listing = stdout(&["10", "--disassemble", "src/ten/sample-input.txt"]);
+    assert_eq!(fs::read_to_string("src/ten/sample-input.txt").unwrap(), listing);
+    let path = temp_file("listing.txt");
+    fs::write(&path, &listing).unwrap();
+    let answers = stdout(&["10", &path]);
+    fs::remove_file(&path).unwrap();
+    assert!(answers.contains("Solution for part 1 13140\n"), "{}", answers);
+}
+
+#[test]
+fn test_draw_roundtrip() {
+    for text in ["FCJAPJRE", "EHIYAHIY"] {
+        let path = temp_file(&format!("{}.txt", text));
+        fs::write(&path, stdout(&["10", "--draw", text])).unwrap();
+        let answers = stdout(&["10", &path]);
+        fs::remove_file(&path).unwrap();
+        assert!(answers.contains(&format!("Solution for part 2 {}\n", text)), "{}", answers);
+    }
+}